@@ -8,7 +8,6 @@ rm -rf docker/rootfs
 mkdir docker/rootfs
 
 cp target/x86_64-unknown-linux-musl/release/docserver docker/rootfs
-cp -r templates docker/rootfs
 
 IMAGE=embassy.dev/docserver:$(date '+%Y%m%d%H%M%S')
 
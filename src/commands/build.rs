@@ -4,6 +4,7 @@ use std::fs;
 use std::io::Write as _;
 use std::path::{Path, PathBuf};
 use std::process::{self, Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use clap::Parser;
 use regex::bytes::Regex as ByteRegex;
@@ -14,7 +15,7 @@ use crate::common::manifest;
 use crate::common::zup::write::pack;
 
 fn should_include_file(path: &Path) -> bool {
-    path.file_name().map_or(true, |f| {
+    path.file_name().is_none_or(|f| {
         f != "implementors" && !f.to_str().unwrap().starts_with('_') && !path.ends_with("!.html")
     })
 }
@@ -67,7 +68,7 @@ impl FlavorProcessor {
 
     fn process_html_file(&self, src_path: &PathBuf, dest_path: &PathBuf) -> anyhow::Result<()> {
         if src_path.extension().and_then(|s| s.to_str()) == Some("html") {
-            let data = fs::read(&src_path)?;
+            let data = fs::read(src_path)?;
 
             let res = self.re_remove_settings.replace_all(&data, &[][..]);
             let res = self.re_remove_hidden_src.replace_all(&res, &[][..]);
@@ -88,9 +89,9 @@ impl FlavorProcessor {
                 .re_fix_root_path
                 .replace_all(&res, &b"data-root-path=\"./"[..]);
 
-            fs::write(&dest_path, &res)?;
+            fs::write(dest_path, &res)?;
         } else {
-            fs::rename(&src_path, &dest_path)?;
+            fs::rename(src_path, dest_path)?;
         };
 
         Ok(())
@@ -257,12 +258,16 @@ pub async fn run(args: BuildArgs) -> anyhow::Result<()> {
     let manifest = load_manifest(&args.input);
 
     let mut cmd = Command::new("git");
-    cmd.args(&["rev-parse", "HEAD"]);
+    cmd.args(["rev-parse", "HEAD"]);
     cmd.current_dir(&args.input);
     let output = cmd.output().unwrap();
     assert!(output.status.success());
     let docserver_info = manifest::DocserverInfo {
         git_commit: String::from_utf8(output.stdout).unwrap(),
+        build_time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs()),
     };
     let docserver_info_bytes = serde_json::to_vec(&docserver_info).unwrap();
 
@@ -286,30 +291,28 @@ pub async fn run(args: BuildArgs) -> anyhow::Result<()> {
         let mut stdin = child.stdin.take().unwrap();
 
         for (i, flavor) in flavors.iter().enumerate() {
-            let mut cmdargs = Vec::<String>::new();
-
-            cmdargs.push("rustdoc".to_string());
-            cmdargs.push("--manifest-path".to_string());
-            cmdargs.push(args.input.join("Cargo.toml").to_str().unwrap().to_string());
-            cmdargs.push("--artifact-dir".to_string());
-            cmdargs.push(
+            let mut cmdargs = vec![
+                "rustdoc".to_string(),
+                "--manifest-path".to_string(),
+                args.input.join("Cargo.toml").to_str().unwrap().to_string(),
+                "--artifact-dir".to_string(),
                 cargo_out_dir
                     .join(i.to_string())
                     .to_str()
                     .unwrap()
                     .to_string(),
-            );
-            cmdargs.push("--features".to_string());
-            cmdargs.push(flavor.features.join(",").to_string());
-            cmdargs.push("--target".to_string());
-            cmdargs.push(flavor.target.to_string());
-            cmdargs.push("--".to_string());
-            cmdargs.push("-Zunstable-options".to_string());
-            cmdargs.push("--static-root-path".to_string());
-            cmdargs.push("/static/".to_string());
+                "--features".to_string(),
+                flavor.features.join(",").to_string(),
+                "--target".to_string(),
+                flavor.target.to_string(),
+                "--".to_string(),
+                "-Zunstable-options".to_string(),
+                "--static-root-path".to_string(),
+                "/static/".to_string(),
+            ];
 
             for (dep_name, dep) in &manifest.dependencies {
-                if let Some(_) = &dep.path {
+                if dep.path.is_some() {
                     cmdargs.push(format!(
                         "--extern-html-root-url={}=/__DOCSERVER_DEPLINK/{}/",
                         dep_name.replace('-', "_"),
@@ -380,30 +383,30 @@ pub async fn run(args: BuildArgs) -> anyhow::Result<()> {
         fs::create_dir_all(&flavor_output_dir)?;
 
         // Copy and process the documentation files
-        FlavorProcessor::new(&crate_name)
+        FlavorProcessor::new(crate_name)
             .copy_and_process_dir(&doc_crate_dir, &flavor_output_dir)?;
 
         // Copy static files only once
-        if let Some(static_path) = &args.output_static {
-            if !statics_copied {
-                fs::create_dir_all(static_path).unwrap();
-                // recursive copy
-                let doc_static_dir = doc_dir.join("static.files");
-                let mut stack = vec![doc_static_dir.clone()];
-                while let Some(path) = stack.pop() {
-                    if path.is_dir() {
-                        for entry in fs::read_dir(path).unwrap() {
-                            stack.push(entry.unwrap().path());
-                        }
-                    } else {
-                        let rel_path = path.strip_prefix(&doc_static_dir).unwrap();
-                        let target_path = static_path.join(rel_path);
-                        let _ = fs::create_dir_all(target_path.parent().unwrap());
-                        fs::copy(path, target_path).unwrap();
+        if let Some(static_path) = &args.output_static
+            && !statics_copied
+        {
+            fs::create_dir_all(static_path).unwrap();
+            // recursive copy
+            let doc_static_dir = doc_dir.join("static.files");
+            let mut stack = vec![doc_static_dir.clone()];
+            while let Some(path) = stack.pop() {
+                if path.is_dir() {
+                    for entry in fs::read_dir(path).unwrap() {
+                        stack.push(entry.unwrap().path());
                     }
+                } else {
+                    let rel_path = path.strip_prefix(&doc_static_dir).unwrap();
+                    let target_path = static_path.join(rel_path);
+                    let _ = fs::create_dir_all(target_path.parent().unwrap());
+                    fs::copy(path, target_path).unwrap();
                 }
-                statics_copied = true;
             }
+            statics_copied = true;
         }
     }
 
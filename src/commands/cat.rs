@@ -0,0 +1,26 @@
+use clap::Parser;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::common::zup::read::Reader;
+
+#[derive(Parser)]
+pub struct CatArgs {
+    /// Path to the .zup archive
+    pub archive: PathBuf,
+    /// File inside the archive to print
+    pub path: String,
+}
+
+pub async fn run(args: CatArgs) -> anyhow::Result<()> {
+    let zup = Reader::new(&args.archive)?;
+
+    let path: Vec<&str> = args.path.split('/').filter(|s| !s.is_empty()).collect();
+    let data = zup.read(&path)?;
+
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&data)?;
+    stdout.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use clap::Parser;
+use similar::TextDiff;
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::str;
+
+use crate::common::zup::{
+    layout,
+    read::{Directory, File, Node, Reader},
+};
+
+/// Extensions of files that get a text diff with `--text`.
+const TEXT_EXTENSIONS: &[&str] = &["html", "js", "css", "json", "toml", "txt", "svg", "md"];
+
+struct Differ<'a> {
+    a: &'a Reader,
+    b: &'a Reader,
+    text: bool,
+    added: usize,
+    removed: usize,
+    changed: usize,
+    /// Node pairs already known to be identical. Dedup makes the same pair
+    /// show up under many paths (e.g. files shared between flavors).
+    same: HashSet<(layout::Range, layout::Range)>,
+}
+
+impl<'a> Differ<'a> {
+    pub fn new(a: &'a Reader, b: &'a Reader, text: bool) -> Self {
+        Self {
+            a,
+            b,
+            text,
+            added: 0,
+            removed: 0,
+            changed: 0,
+            same: HashSet::new(),
+        }
+    }
+
+    pub fn diff_dir(
+        &mut self,
+        a: &Directory<'_>,
+        b: &Directory<'_>,
+        path: &str,
+    ) -> anyhow::Result<bool> {
+        let key = (a.node().range, b.node().range);
+        if self.same.contains(&key) {
+            return Ok(true);
+        }
+
+        let a_children: BTreeMap<_, _> = a.children()?.into_iter().collect();
+        let mut b_children: BTreeMap<_, _> = b.children()?.into_iter().collect();
+
+        let mut same = true;
+        for (name, a) in a_children {
+            let child_path = format!("{}{}", path, name);
+            let Some(b) = b_children.remove(&name) else {
+                println!("- {}{}", child_path, suffix(&a));
+                self.removed += 1;
+                same = false;
+                continue;
+            };
+
+            same &= match (&a, &b) {
+                (Node::Directory(a), Node::Directory(b)) => {
+                    self.diff_dir(a, b, &format!("{}/", child_path))?
+                }
+                (Node::File(a), Node::File(b)) => self.diff_file(a, b, &child_path)?,
+                _ => {
+                    println!("M {}{} -> {}", child_path, suffix(&a), suffix(&b));
+                    self.changed += 1;
+                    false
+                }
+            };
+        }
+        for (name, b) in b_children {
+            println!("+ {}{}{}", path, name, suffix(&b));
+            self.added += 1;
+            same = false;
+        }
+
+        if same {
+            self.same.insert(key);
+        }
+        Ok(same)
+    }
+
+    fn diff_file(&mut self, a: &File<'_>, b: &File<'_>, path: &str) -> anyhow::Result<bool> {
+        let key = (a.node().range, b.node().range);
+        if self.same.contains(&key) {
+            return Ok(true);
+        }
+
+        // Stored checksums let us compare without decompressing anything.
+        let same = match (self.a.checksum(a.node())?, self.b.checksum(b.node())?) {
+            (Some(a), Some(b)) => a == b,
+            _ => a.size()? == b.size()? && a.read()? == b.read()?,
+        };
+        if same {
+            self.same.insert(key);
+            return Ok(true);
+        }
+
+        println!("M {}", path);
+        self.changed += 1;
+
+        let is_text = TEXT_EXTENSIONS.contains(&path.rsplit('.').next().unwrap_or(""));
+        if self.text && is_text {
+            let (a_data, b_data) = (a.read()?, b.read()?);
+            if let (Ok(a_text), Ok(b_text)) = (str::from_utf8(&a_data), str::from_utf8(&b_data)) {
+                let diff = TextDiff::from_lines(a_text, b_text);
+                print!(
+                    "{}",
+                    diff.unified_diff()
+                        .header(&format!("a/{}", path), &format!("b/{}", path))
+                );
+            }
+        }
+        Ok(false)
+    }
+}
+
+fn suffix(n: &Node<'_>) -> &'static str {
+    match n {
+        Node::Directory(_) => "/",
+        Node::File(_) => "",
+    }
+}
+
+#[derive(Parser)]
+pub struct DiffArgs {
+    /// Old .zup archive
+    pub old: PathBuf,
+    /// New .zup archive
+    pub new: PathBuf,
+    /// Print a unified diff of changed text files
+    #[clap(long)]
+    pub text: bool,
+}
+
+pub async fn run(args: DiffArgs) -> anyhow::Result<()> {
+    let a = Reader::new(&args.old)?;
+    let b = Reader::new(&args.new)?;
+
+    let (Node::Directory(a_root), Node::Directory(b_root)) = (a.root_node(), b.root_node()) else {
+        unreachable!("root node is always a directory");
+    };
+
+    let mut d = Differ::new(&a, &b, args.text);
+    d.diff_dir(&a_root, &b_root, "")?;
+
+    println!("added {}", d.added);
+    println!("removed {}", d.removed);
+    println!("changed {}", d.changed);
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use clap::Parser;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::PathBuf;
+
+use crate::common::zup::{
+    layout,
+    read::{Node, Reader},
+};
+
+/// Counts how many directory entries point at each node, to spot dedup-shared nodes.
+fn count_refs(
+    n: &Node<'_>,
+    refs: &mut HashMap<layout::Range, usize>,
+    seen: &mut HashSet<layout::Range>,
+) -> io::Result<()> {
+    let Node::Directory(dir) = n else {
+        return Ok(());
+    };
+    if !seen.insert(dir.node().range) {
+        return Ok(());
+    }
+    for (_, c) in dir.children()? {
+        *refs.entry(c.node().range).or_default() += 1;
+        count_refs(&c, refs, seen)?;
+    }
+    Ok(())
+}
+
+#[derive(Parser)]
+pub struct LsArgs {
+    /// Path to the .zup archive
+    pub archive: PathBuf,
+    /// Directory inside the archive to list, defaults to the root
+    #[clap(default_value = "")]
+    pub path: String,
+    /// Mark entries shared with other paths by dedup. Reads every directory in the archive
+    #[clap(long)]
+    pub shared: bool,
+}
+
+pub async fn run(args: LsArgs) -> anyhow::Result<()> {
+    let zup = Reader::new(&args.archive)?;
+
+    let path: Vec<&str> = args.path.split('/').filter(|s| !s.is_empty()).collect();
+    let node = zup.open(&path)?;
+
+    let mut refs = HashMap::new();
+    if args.shared {
+        count_refs(&zup.root_node(), &mut refs, &mut HashSet::new())?;
+    }
+
+    let entries = match node {
+        Node::Directory(dir) => dir.children()?,
+        Node::File(_) => vec![(path.last().unwrap().to_string(), node)],
+    };
+
+    // Flags: `z` compressed, `s` shared with other paths by dedup (only with --shared).
+    println!("{:<5} {:>12} {:>12}  NAME", "FLAGS", "STORED", "SIZE");
+    for (name, c) in entries {
+        let n = c.node();
+        let compressed = if n.flags & layout::FLAG_COMPRESSED != 0 {
+            'z'
+        } else {
+            '-'
+        };
+        let shared = if refs.get(&n.range).copied().unwrap_or(0) > 1 {
+            's'
+        } else {
+            '-'
+        };
+        let (kind, size, suffix) = match &c {
+            Node::Directory(_) => ('d', "-".to_string(), "/"),
+            Node::File(f) => ('-', f.size()?.to_string(), ""),
+        };
+        println!(
+            "{}{}{}   {:>12} {:>12}  {}{}",
+            kind, compressed, shared, n.range.len, size, name, suffix
+        );
+    }
+
+    Ok(())
+}
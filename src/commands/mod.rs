@@ -1,5 +1,9 @@
 pub mod build;
 pub mod build_release;
+pub mod cat;
+pub mod diff;
+pub mod ls;
 pub mod serve;
 pub mod unzup;
+pub mod verify;
 pub mod zup;
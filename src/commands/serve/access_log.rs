@@ -0,0 +1,104 @@
+use chrono::Utc;
+use clap::ValueEnum;
+use hyper::{Method, StatusCode, Uri, Version};
+use log::info;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum AccessLogFormat {
+    /// Short human-readable line through the regular logger
+    #[default]
+    Text,
+    /// One JSON object per line on stdout
+    Json,
+    /// Apache/nginx combined log format on stdout, with request ID, duration and crate appended
+    Combined,
+}
+
+/// The crate, version and flavor a request was served from, attached to the response as an extension.
+#[derive(Clone, Debug)]
+pub struct Resolved {
+    pub krate: String,
+    pub version: String,
+    pub flavor: String,
+}
+
+pub struct Entry<'a> {
+    pub id: &'a str,
+    pub client: Option<SocketAddr>,
+    pub method: &'a Method,
+    pub uri: &'a Uri,
+    pub http_version: Version,
+    pub referer: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+    pub status: StatusCode,
+    pub bytes: Option<u64>,
+    pub duration: Duration,
+    pub resolved: Option<&'a Resolved>,
+}
+
+/// Use the request ID a proxy in front of us assigned, if it looks sane.
+pub fn request_id(header: Option<&str>) -> String {
+    match header {
+        Some(id)
+            if !id.is_empty()
+                && id.len() <= 64
+                && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') =>
+        {
+            id.to_string()
+        }
+        _ => format!("{:016x}", rand::random::<u64>()),
+    }
+}
+
+impl AccessLogFormat {
+    pub fn write(self, e: &Entry<'_>) {
+        let now = Utc::now();
+        let client = e.client.map(|a| a.ip().to_string());
+
+        match self {
+            AccessLogFormat::Text => info!("{} {}: {}", e.method, e.uri, e.status),
+            AccessLogFormat::Json => {
+                let json = serde_json::json!({
+                    "time": now.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+                    "request_id": e.id,
+                    "client": client,
+                    "method": e.method.as_str(),
+                    "uri": e.uri.to_string(),
+                    "http_version": format!("{:?}", e.http_version),
+                    "status": e.status.as_u16(),
+                    "bytes": e.bytes,
+                    "duration_ms": e.duration.as_secs_f64() * 1000.0,
+                    "referer": e.referer,
+                    "user_agent": e.user_agent,
+                    "crate": e.resolved.map(|r| &r.krate),
+                    "version": e.resolved.map(|r| &r.version),
+                    "flavor": e.resolved.map(|r| &r.flavor),
+                });
+                println!("{}", json);
+            }
+            AccessLogFormat::Combined => {
+                let resolved = match e.resolved {
+                    Some(r) => format!("{}/{}/{}", r.krate, r.version, r.flavor),
+                    None => "-".to_string(),
+                };
+                println!(
+                    "{} - - [{}] \"{} {} {:?}\" {} {} \"{}\" \"{}\" {} {:.3} {}",
+                    client.as_deref().unwrap_or("-"),
+                    now.format("%d/%b/%Y:%H:%M:%S +0000"),
+                    e.method,
+                    e.uri,
+                    e.http_version,
+                    e.status.as_u16(),
+                    e.bytes.map_or("-".to_string(), |b| b.to_string()),
+                    e.referer.unwrap_or("-").replace('"', "\\\""),
+                    e.user_agent.unwrap_or("-").replace('"', "\\\""),
+                    e.id,
+                    e.duration.as_secs_f64(),
+                    resolved,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use bytes::Bytes;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Frame, SizeHint};
+use std::io::{self, Read};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+use crate::common::zup::read::{Node, Reader};
+
+pub type Body = BoxBody<Bytes, io::Error>;
+
+/// Size of the chunks streamed bodies are sent in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub fn full(data: impl Into<Bytes>) -> Body {
+    Full::new(data.into()).map_err(|e| match e {}).boxed()
+}
+
+/// What `stream` sends of a file.
+enum Content {
+    /// `len` bytes of the uncompressed file, starting at `offset`.
+    Decompressed { offset: u64 },
+    /// The stored zstd frame, see `File::zstd_frame`.
+    ZstdFrame,
+}
+
+/// Stream a file out of a zup, decompressing it incrementally on a blocking thread.
+///
+/// Sends `len` bytes of the uncompressed file starting at `offset`. Everything before `offset`
+/// still has to be decompressed, it's just not sent.
+pub fn stream_file(zup: Arc<Reader>, path: Vec<String>, offset: u64, len: u64) -> Body {
+    stream(zup, path, Content::Decompressed { offset }, len)
+}
+
+/// Stream a file's stored zstd frame, of `len` bytes, out of a zup without decompressing it.
+pub fn stream_zstd_frame(zup: Arc<Reader>, path: Vec<String>, len: u64) -> Body {
+    stream(zup, path, Content::ZstdFrame, len)
+}
+
+fn stream(zup: Arc<Reader>, path: Vec<String>, content: Content, len: u64) -> Body {
+    let (tx, rx) = mpsc::channel(4);
+
+    tokio::task::spawn_blocking(move || {
+        let res = (|| {
+            let path: Vec<&str> = path.iter().map(|s| s.as_str()).collect();
+            let Node::File(f) = zup.open(&path)? else {
+                return Err(io::Error::new(
+                    io::ErrorKind::IsADirectory,
+                    "is a directory, not a file",
+                ));
+            };
+            let mut r: Box<dyn Read> = match content {
+                Content::Decompressed { offset } => {
+                    let mut r = f.reader()?;
+                    io::copy(&mut r.by_ref().take(offset), &mut io::sink())?;
+                    Box::new(r.take(len))
+                }
+                Content::ZstdFrame => match f.zstd_frame()? {
+                    Some(r) => Box::new(r.take(len)),
+                    None => return Err(io::Error::other("not a standalone zstd frame")),
+                },
+            };
+            loop {
+                let mut buf = vec![0; CHUNK_SIZE];
+                let n = r.read(&mut buf)?;
+                if n == 0 {
+                    return Ok(());
+                }
+                buf.truncate(n);
+                if tx.blocking_send(Ok(Bytes::from(buf))).is_err() {
+                    // Client went away.
+                    return Ok(());
+                }
+            }
+        })();
+        if let Err(e) = res {
+            log::error!("error streaming {}: {:?}", path.join("/"), e);
+            let _ = tx.blocking_send(Err(e));
+        }
+    });
+
+    ChannelBody { rx, remaining: len }.boxed()
+}
+
+struct ChannelBody {
+    rx: mpsc::Receiver<io::Result<Bytes>>,
+    remaining: u64,
+}
+
+impl hyper::body::Body for ChannelBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, io::Error>>> {
+        match self.rx.poll_recv(cx) {
+            Poll::Ready(Some(Ok(data))) => {
+                self.remaining = self.remaining.saturating_sub(data.len() as u64);
+                Poll::Ready(Some(Ok(Frame::data(data))))
+            }
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining)
+    }
+}
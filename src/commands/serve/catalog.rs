@@ -0,0 +1,247 @@
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::common::zup::read::{Node, Reader};
+
+/// Snapshot of the crates, versions and flavors available in the webroot.
+///
+/// Built by scanning `crates/`, and rebuilt periodically. Archives that haven't
+/// changed since the previous snapshot aren't reopened.
+#[derive(Default)]
+pub struct Catalog {
+    crates: BTreeMap<String, Vec<Version>>,
+    broken: Vec<Broken>,
+}
+
+pub struct Version {
+    pub name: String,
+    pub flavors: Vec<String>,
+    path: PathBuf,
+    /// Size of the archive in bytes.
+    pub len: u64,
+    pub mtime: Option<SystemTime>,
+}
+
+/// An archive that couldn't be opened, or doesn't look like crate docs.
+#[derive(Serialize)]
+pub struct Broken {
+    pub path: PathBuf,
+    pub error: String,
+    #[serde(skip)]
+    len: u64,
+    #[serde(skip)]
+    mtime: Option<SystemTime>,
+}
+
+#[derive(Serialize)]
+pub struct CatalogStats<'a> {
+    pub crates: usize,
+    pub versions: usize,
+    pub broken: &'a [Broken],
+}
+
+/// Newest first, with `git` before all releases.
+fn version_order(a: &str, b: &str) -> Ordering {
+    match (a, b) {
+        ("git", "git") => Ordering::Equal,
+        ("git", _) => Ordering::Less,
+        (_, "git") => Ordering::Greater,
+        _ => {
+            let av = semver::Version::parse(a).ok();
+            let bv = semver::Version::parse(b).ok();
+            bv.cmp(&av)
+        }
+    }
+}
+
+fn read_flavors(path: &Path) -> Result<Vec<String>, String> {
+    let zup = Reader::new(path).map_err(|e| e.to_string())?;
+    flavors(&zup).map_err(|e| e.to_string())
+}
+
+/// The flavors in an archive, sorted. Fails if there are none.
+pub fn flavors(zup: &Reader) -> io::Result<Vec<String>> {
+    let Node::Directory(dir) = zup.open(&["flavors"])? else {
+        return Err(io::Error::other("flavors is not a directory"));
+    };
+    let mut res: Vec<_> = dir.children()?.into_iter().map(|(name, _)| name).collect();
+    if res.is_empty() {
+        return Err(io::Error::other("no flavors"));
+    }
+    res.sort();
+    Ok(res)
+}
+
+impl Catalog {
+    /// Scan `crates_path`, reusing what `prev` knows about archives that haven't changed.
+    pub fn scan(crates_path: &Path, prev: &Catalog) -> io::Result<Self> {
+        let mut known = HashMap::new();
+        for v in prev.crates.values().flatten() {
+            known.insert(v.path.as_path(), (v.len, v.mtime, Ok(&v.flavors)));
+        }
+        for b in &prev.broken {
+            known.insert(b.path.as_path(), (b.len, b.mtime, Err(&b.error)));
+        }
+
+        // Only failing to list `crates_path` itself fails the scan. Anything below it that can't
+        // be read is skipped or recorded as broken, so one bad entry doesn't keep the catalog stale.
+        let mut res = Catalog::default();
+        for f in fs::read_dir(crates_path)? {
+            let Ok(f) = f else {
+                continue;
+            };
+            if !f.file_type().is_ok_and(|t| t.is_dir()) {
+                continue;
+            }
+            let Some(krate) = f.file_name().to_str().map(|s| s.to_string()) else {
+                continue;
+            };
+
+            let entries = match fs::read_dir(f.path()) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    log::warn!("unreadable crate directory {}: {}", f.path().display(), e);
+                    res.broken.push(Broken {
+                        path: f.path(),
+                        error: e.to_string(),
+                        len: 0,
+                        mtime: None,
+                    });
+                    continue;
+                }
+            };
+            let mut versions = Vec::new();
+            for f in entries {
+                let Ok(f) = f else {
+                    continue;
+                };
+                let name = f.file_name();
+                let Some(version) = name.to_str().and_then(|n| n.strip_suffix(".zup")) else {
+                    continue;
+                };
+                let path = f.path();
+                let m = match f.metadata() {
+                    Ok(m) => m,
+                    // Removed since it was listed, e.g. while being replaced.
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => {
+                        log::warn!("broken archive {}: {}", path.display(), e);
+                        res.broken.push(Broken {
+                            path,
+                            error: e.to_string(),
+                            len: 0,
+                            mtime: None,
+                        });
+                        continue;
+                    }
+                };
+                let (len, mtime) = (m.len(), m.modified().ok());
+
+                let flavors = match known.get(path.as_path()) {
+                    Some((l, t, flavors)) if *l == len && *t == mtime => {
+                        flavors.cloned().map_err(|e| e.clone())
+                    }
+                    _ => {
+                        let flavors = read_flavors(&path);
+                        if let Err(e) = &flavors {
+                            log::warn!("broken archive {}: {}", path.display(), e);
+                        }
+                        flavors
+                    }
+                };
+                match flavors {
+                    Ok(flavors) => versions.push(Version {
+                        name: version.to_string(),
+                        flavors,
+                        path,
+                        len,
+                        mtime,
+                    }),
+                    Err(error) => res.broken.push(Broken {
+                        path,
+                        error,
+                        len,
+                        mtime,
+                    }),
+                }
+            }
+
+            if !versions.is_empty() {
+                versions.sort_by(|a, b| version_order(&a.name, &b.name));
+                res.crates.insert(krate, versions);
+            }
+        }
+        res.broken.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(res)
+    }
+
+    /// Whether `other` lists different archives than `self`.
+    pub fn differs(&self, other: &Catalog) -> bool {
+        let key = |c: &Catalog| {
+            let mut res: Vec<_> = c
+                .crates
+                .values()
+                .flatten()
+                .map(|v| (v.path.clone(), v.len, v.mtime))
+                .collect();
+            res.extend(c.broken.iter().map(|b| (b.path.clone(), b.len, b.mtime)));
+            res.sort();
+            res
+        };
+        key(self) != key(other)
+    }
+
+    pub fn crates(&self) -> impl Iterator<Item = &str> {
+        self.crates.keys().map(|s| s.as_str())
+    }
+
+    /// Versions of a crate, newest first.
+    pub fn versions(&self, krate: &str) -> Option<&[Version]> {
+        self.crates.get(krate).map(|v| v.as_slice())
+    }
+
+    pub fn version(&self, krate: &str, version: &str) -> Option<&Version> {
+        self.versions(krate)?.iter().find(|v| v.name == version)
+    }
+
+    /// The newest release of a crate, or `git` if it has none.
+    pub fn latest(&self, krate: &str) -> Option<&Version> {
+        let versions = self.versions(krate)?;
+        versions
+            .iter()
+            .find(|v| v.name != "git")
+            .or(versions.first())
+    }
+
+    /// Resolve a version as it appears in a URL: an exact version, `latest`, or a semver
+    /// requirement like `~0.5`, which picks the newest matching release.
+    pub fn resolve(&self, krate: &str, version: &str) -> Option<&Version> {
+        if let Some(v) = self.version(krate, version) {
+            return Some(v);
+        }
+        if version == "latest" {
+            return self.latest(krate);
+        }
+        // A missing exact version stays missing rather than silently becoming a different one.
+        if semver::Version::parse(version).is_ok() {
+            return None;
+        }
+        let req = semver::VersionReq::parse(version).ok()?;
+        self.versions(krate)?
+            .iter()
+            .find(|v| semver::Version::parse(&v.name).is_ok_and(|v| req.matches(&v)))
+    }
+
+    pub fn stats(&self) -> CatalogStats<'_> {
+        CatalogStats {
+            crates: self.crates.len(),
+            versions: self.crates.values().map(|v| v.len()).sum(),
+            broken: &self.broken,
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use flate2::write::GzEncoder;
+use hyper::Request;
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    Identity,
+    Zstd,
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    /// Value for the `Content-Encoding` header.
+    pub fn name(self) -> Option<&'static str> {
+        match self {
+            Encoding::Identity => None,
+            Encoding::Zstd => Some("zstd"),
+            Encoding::Brotli => Some("br"),
+            Encoding::Gzip => Some("gzip"),
+        }
+    }
+
+    /// Derive the ETag of the encoded representation from the identity one.
+    pub fn etag(self, etag: &str) -> String {
+        match (self.name(), etag.strip_suffix('"')) {
+            (Some(name), Some(etag)) => format!("{}-{}\"", etag, name),
+            _ => etag.to_string(),
+        }
+    }
+}
+
+/// Quality the client gave `name` in `Accept-Encoding`, or `None` if it wasn't mentioned.
+fn quality(accept: &str, name: &str) -> Option<f32> {
+    let mut wildcard = None;
+    for item in accept.split(',') {
+        let mut parts = item.split(';');
+        let coding = parts.next().unwrap_or("").trim();
+        let q = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse().ok())
+            .unwrap_or(1.0);
+        if coding.eq_ignore_ascii_case(name) {
+            return Some(q);
+        }
+        if coding == "*" {
+            wildcard = Some(q);
+        }
+    }
+    wildcard
+}
+
+/// Pick the encoding for a response.
+///
+/// `stored_zstd` says whether a standalone zstd frame is available to forward as-is,
+/// which is preferred since it costs nothing. Otherwise brotli beats gzip.
+pub fn negotiate<B>(req: &Request<B>, stored_zstd: bool) -> Encoding {
+    let Some(accept) = req
+        .headers()
+        .get("Accept-Encoding")
+        .and_then(|h| h.to_str().ok())
+    else {
+        return Encoding::Identity;
+    };
+
+    let accepts = |name| quality(accept, name).is_some_and(|q| q > 0.0);
+    if stored_zstd && accepts("zstd") {
+        Encoding::Zstd
+    } else if accepts("br") {
+        Encoding::Brotli
+    } else if accepts("gzip") {
+        Encoding::Gzip
+    } else {
+        Encoding::Identity
+    }
+}
+
+/// Whether compressing content of this type on the fly is worth it.
+pub fn compressible(mime: &str) -> bool {
+    mime.starts_with("text/")
+        || matches!(
+            mime,
+            "application/javascript"
+                | "application/json"
+                | "image/svg+xml"
+                | "application/x-font-ttf"
+        )
+}
+
+/// Compress `data` on the fly. Zstd is never produced here, only forwarded from storage.
+pub fn compress(enc: Encoding, data: &[u8]) -> io::Result<Vec<u8>> {
+    match enc {
+        Encoding::Brotli => {
+            let mut w = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+            w.write_all(data)?;
+            Ok(w.into_inner())
+        }
+        Encoding::Gzip => {
+            let mut w = GzEncoder::new(Vec::new(), flate2::Compression::new(6));
+            w.write_all(data)?;
+            w.finish()
+        }
+        Encoding::Identity | Encoding::Zstd => Ok(data.to_vec()),
+    }
+}
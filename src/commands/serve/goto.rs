@@ -0,0 +1,83 @@
+use std::io;
+
+use super::search::KINDS;
+use crate::common::zup::read::{Node, Reader};
+
+/// Kinds of pages that can have members linked with an anchor.
+const PARENT_KINDS: &[&str] = &["struct", "enum", "trait", "union", "type", "primitive"];
+
+/// Anchor prefixes rustdoc uses for members, most likely first.
+const MEMBER_ANCHORS: &[&str] = &[
+    "method",
+    "tymethod",
+    "structfield",
+    "variant",
+    "associatedconstant",
+    "associatedtype",
+];
+
+fn page(modules: &[&str], file: &str) -> String {
+    let mut res = String::new();
+    for m in modules {
+        res.push_str(m);
+        res.push('/');
+    }
+    res.push_str(file);
+    res
+}
+
+/// Pages in `flavor` that the Rust path `path` (without the crate name) could refer to.
+///
+/// Looks for a module or an item page named like the last segment, and failing that, for an
+/// item page named like the second to last with a member anchor for the last.
+pub fn resolve(zup: &Reader, flavor: &str, path: &[&str]) -> io::Result<Vec<String>> {
+    let Some((name, modules)) = path.split_last() else {
+        return Ok(vec!["index.html".to_string()]);
+    };
+
+    let mut dir_path = vec!["flavors", flavor];
+    dir_path.extend_from_slice(modules);
+    let mut res = Vec::new();
+    if let Ok(Node::Directory(dir)) = zup.open(&dir_path) {
+        if let Some(Node::Directory(d)) = dir.child(name)?
+            && d.child("index.html")?.is_some()
+        {
+            res.push(page(path, "index.html"));
+        }
+        for kind in KINDS {
+            let file = format!("{}.{}.html", kind, name);
+            if dir.child(&file)?.is_some() {
+                res.push(page(modules, &file));
+            }
+        }
+    }
+    if !res.is_empty() {
+        return Ok(res);
+    }
+
+    let Some((parent, modules)) = modules.split_last() else {
+        return Ok(res);
+    };
+    let mut dir_path = vec!["flavors", flavor];
+    dir_path.extend_from_slice(modules);
+    let Ok(Node::Directory(dir)) = zup.open(&dir_path) else {
+        return Ok(res);
+    };
+    for kind in PARENT_KINDS {
+        let file = format!("{}.{}.html", kind, parent);
+        let Some(Node::File(f)) = dir.child(&file)? else {
+            continue;
+        };
+        let html = f.read()?;
+        let anchor = MEMBER_ANCHORS
+            .iter()
+            .map(|a| format!("{}.{}", a, name))
+            .find(|id| {
+                let needle = format!("id=\"{}\"", id);
+                html.windows(needle.len()).any(|w| w == needle.as_bytes())
+            })
+            .unwrap_or_else(|| format!("method.{}", name));
+        res.push(format!("{}#{}", page(modules, &file), anchor));
+    }
+    Ok(res)
+}
@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::common::zup::cache::ReaderCacheStats;
+use crate::common::zup::read::Counters;
+
+/// Upper bounds of the request latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Coarse kind of route a request path goes to, for labelling metrics.
+pub fn route_kind(path: &str) -> &'static str {
+    let parts: Vec<_> = path.split('/').filter(|p| !p.is_empty()).collect();
+    match parts[..] {
+        ["static", ..] => "static",
+        ["api", ..] | ["metrics"] => "api",
+        ["healthz"] | ["readyz"] => "health",
+        ["go", ..] | [] | [_] | [_, _] => "redirect",
+        _ => "crate",
+    }
+}
+
+/// Request and archive metrics, exposed in the Prometheus text format.
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+    zup: Mutex<BTreeMap<String, Arc<Counters>>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    requests: BTreeMap<(&'static str, u16), u64>,
+    latency: BTreeMap<&'static str, Histogram>,
+    bytes: BTreeMap<&'static str, u64>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// Per-bucket counts, not cumulative. The last one is `+Inf`.
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Metrics {
+    pub fn record(&self, route: &'static str, status: u16, elapsed: Duration, bytes: Option<u64>) {
+        let secs = elapsed.as_secs_f64();
+        let mut inner = self.inner.lock().unwrap();
+        *inner.requests.entry((route, status)).or_default() += 1;
+
+        let h = inner.latency.entry(route).or_default();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|b| secs <= *b)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        h.buckets[bucket] += 1;
+        h.sum += secs;
+        h.count += 1;
+
+        if let Some(bytes) = bytes {
+            *inner.bytes.entry(route).or_default() += bytes;
+        }
+    }
+
+    /// Counters for the archives of `krate`.
+    pub fn zup_counters(&self, krate: &str) -> Arc<Counters> {
+        self.zup
+            .lock()
+            .unwrap()
+            .entry(krate.to_string())
+            .or_default()
+            .clone()
+    }
+
+    pub fn render(&self, readers: &ReaderCacheStats, broken_archives: usize) -> String {
+        let mut w = String::new();
+        let inner = self.inner.lock().unwrap();
+
+        let name = "docserver_requests_total";
+        header(
+            &mut w,
+            name,
+            "counter",
+            "Requests handled, by route kind and status.",
+        );
+        for ((route, status), n) in &inner.requests {
+            let labels = format!("route=\"{route}\",status=\"{status}\"");
+            sample(&mut w, name, &labels, n);
+        }
+
+        let name = "docserver_request_duration_seconds";
+        header(
+            &mut w,
+            name,
+            "histogram",
+            "Time until the response headers were ready.",
+        );
+        for (route, h) in &inner.latency {
+            let mut cumulative = 0;
+            for (i, n) in h.buckets.iter().enumerate() {
+                cumulative += n;
+                let le = match LATENCY_BUCKETS.get(i) {
+                    Some(b) => b.to_string(),
+                    None => "+Inf".to_string(),
+                };
+                let labels = format!("route=\"{route}\",le=\"{le}\"");
+                sample(&mut w, &format!("{name}_bucket"), &labels, cumulative);
+            }
+            let labels = format!("route=\"{route}\"");
+            sample(&mut w, &format!("{name}_sum"), &labels, h.sum);
+            sample(&mut w, &format!("{name}_count"), &labels, h.count);
+        }
+
+        let name = "docserver_response_bytes_total";
+        header(
+            &mut w,
+            name,
+            "counter",
+            "Response body bytes served, by route kind.",
+        );
+        for (route, n) in &inner.bytes {
+            sample(&mut w, name, &format!("route=\"{route}\""), n);
+        }
+        drop(inner);
+
+        let zup = self.zup.lock().unwrap();
+        let mut per_crate = |name, help, get: fn(&Counters) -> &AtomicU64| {
+            header(&mut w, name, "counter", help);
+            for (krate, c) in zup.iter() {
+                let labels = format!("crate=\"{}\"", escape(krate));
+                sample(&mut w, name, &labels, get(c).load(Ordering::Relaxed));
+            }
+        };
+        per_crate(
+            "docserver_zup_opens_total",
+            "Archives opened, by crate.",
+            |c| &c.opens,
+        );
+        per_crate(
+            "docserver_zup_decompressions_total",
+            "Archive nodes decompressed, by crate.",
+            |c| &c.decompressions,
+        );
+        per_crate(
+            "docserver_zup_errors_total",
+            "Errors opening or reading archives, by crate.",
+            |c| &c.errors,
+        );
+        drop(zup);
+
+        for (name, kind, help, value) in [
+            (
+                "docserver_node_cache_hits_total",
+                "counter",
+                "Decompressed node cache hits.",
+                readers.nodes.hits,
+            ),
+            (
+                "docserver_node_cache_misses_total",
+                "counter",
+                "Decompressed node cache misses.",
+                readers.nodes.misses,
+            ),
+            (
+                "docserver_node_cache_bytes",
+                "gauge",
+                "Bytes held in the decompressed node cache.",
+                readers.nodes.bytes as u64,
+            ),
+            (
+                "docserver_open_archives",
+                "gauge",
+                "Archives currently kept open.",
+                readers.readers.entries as u64,
+            ),
+            (
+                "docserver_broken_archives",
+                "gauge",
+                "Archives in the webroot that failed to open.",
+                broken_archives as u64,
+            ),
+        ] {
+            header(&mut w, name, kind, help);
+            sample(&mut w, name, "", value);
+        }
+
+        w
+    }
+}
+
+fn header(w: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(w, "# HELP {name} {help}").unwrap();
+    writeln!(w, "# TYPE {name} {kind}").unwrap();
+}
+
+fn sample(w: &mut String, name: &str, labels: &str, value: impl Display) {
+    match labels {
+        "" => writeln!(w, "{name} {value}").unwrap(),
+        _ => writeln!(w, "{name}{{{labels}}} {value}").unwrap(),
+    }
+}
@@ -0,0 +1,1477 @@
+mod access_log;
+mod body;
+mod catalog;
+mod encoding;
+mod goto;
+mod listen;
+mod metrics;
+mod range;
+mod rustdoc_index;
+mod search;
+mod suggest;
+mod templates;
+
+use anyhow::Context as _;
+use bytes::Bytes;
+use clap::Parser;
+use hyper::body::{Body as _, Incoming};
+use hyper::header::HeaderValue;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::{GracefulShutdown, Watcher};
+use log::info;
+use regex::bytes::{Captures, Regex as ByteRegex};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use std::{env, fs};
+use tera::Context;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio_rustls::TlsAcceptor;
+
+use self::access_log::{AccessLogFormat, Entry, Resolved};
+use self::body::{Body, full};
+use self::catalog::Catalog;
+use self::encoding::Encoding;
+use self::listen::{Io, ListenAddr, Listener};
+use self::metrics::Metrics;
+use self::range::Ranged;
+use self::search::{Hit, SearchIndex};
+use self::templates::Templates;
+use crate::common::manifest;
+use crate::common::zup::cache::{NodeCache, ReaderCache};
+use crate::common::zup::layout;
+use crate::common::zup::read::{Counters, Node, Reader};
+
+/// Methods every route supports.
+const ALLOW: &str = "GET, HEAD, OPTIONS";
+
+/// Files larger than this are streamed instead of read into memory, unless they need rewriting.
+const STREAM_THRESHOLD: u64 = 1_000_000;
+
+/// How long to wait before accepting again after `accept` fails, e.g. when out of file descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// How long a client gets to complete the TLS handshake.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn extension(path: &str) -> &str {
+    match path.rfind('.') {
+        Some(x) => &path[x + 1..],
+        None => "",
+    }
+}
+
+fn mime_type(extension: &str) -> &'static str {
+    match extension {
+        "html" => "text/html",
+        "css" => "text/css",
+        "js" => "application/javascript",
+        "json" => "application/json",
+        "ttf" => "application/x-font-ttf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" => "image/jpeg",
+        "txt" => "text/plain",
+        _ => "application/octet-stream", // unknown
+    }
+}
+
+/// Strong ETag for a node, from its stored checksum if the archive has one.
+fn node_etag(zup: &Reader, node: layout::Node) -> io::Result<String> {
+    let hash = match zup.checksum(node)? {
+        Some(hash) => blake3::Hash::from(hash),
+        // Without checksums, identify the node by where it lives in this particular archive file.
+        None => {
+            let mut h = blake3::Hasher::new();
+            h.update(&zup.file_size().to_le_bytes());
+            if let Some(d) = zup
+                .modified()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            {
+                h.update(&d.as_nanos().to_le_bytes());
+            }
+            h.update(&node.range.to_bytes());
+            h.finalize()
+        }
+    };
+    Ok(format_etag(hash))
+}
+
+fn content_etag(data: &[u8]) -> String {
+    format_etag(blake3::hash(data))
+}
+
+fn format_etag(hash: blake3::Hash) -> String {
+    format!("\"{}\"", &hash.to_hex()[..32])
+}
+
+/// Whether the request's `If-None-Match` matches `etag`, using weak comparison.
+fn if_none_match(req: &Request<Incoming>, etag: &str) -> bool {
+    let Some(h) = req
+        .headers()
+        .get("If-None-Match")
+        .and_then(|h| h.to_str().ok())
+    else {
+        return false;
+    };
+    h.split(',')
+        .map(|t| t.trim())
+        .any(|t| t == "*" || t.strip_prefix("W/").unwrap_or(t) == etag)
+}
+
+fn percent_decode(s: &str) -> String {
+    let s = s.as_bytes();
+    let hex = |i: usize| {
+        let d = s.get(i).and_then(|b| (*b as char).to_digit(16))?;
+        Some(d as u8)
+    };
+    let mut res = Vec::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        match (s[i], hex(i + 1), hex(i + 2)) {
+            (b'%', Some(hi), Some(lo)) => {
+                res.push(hi << 4 | lo);
+                i += 2;
+            }
+            (b'+', _, _) => res.push(b' '),
+            (b, _, _) => res.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&res).into_owned()
+}
+
+/// Parameters from the request's query string. Later ones win over earlier ones with the same name.
+fn query_params(req: &Request<Incoming>) -> HashMap<String, String> {
+    let mut res = HashMap::new();
+    for pair in req.uri().query().unwrap_or("").split('&') {
+        if pair.is_empty() {
+            continue;
+        }
+        let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+        res.insert(percent_decode(k), percent_decode(v));
+    }
+    res
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// How many other versions of a crate to look for a missing page in. Each is an archive to open,
+/// and only the requested flavor is looked at.
+const MAX_SUGGESTED_VERSIONS: usize = 5;
+
+/// How many other flavors of the requested version to look for a missing page in.
+const MAX_SUGGESTED_FLAVORS: usize = 5;
+
+/// How many links a 404 page suggests at most.
+const MAX_SUGGESTIONS: usize = 10;
+
+/// A link to where a missing page can be found instead.
+#[derive(Serialize)]
+struct Suggestion {
+    url: String,
+    text: String,
+}
+
+struct Thing {
+    path: PathBuf,
+    templates: Templates,
+    readers: Arc<ReaderCache>,
+    catalog: RwLock<Arc<Catalog>>,
+    metrics: Metrics,
+    search: SearchIndex,
+    access_log: AccessLogFormat,
+    compress_responses: bool,
+}
+
+impl Thing {
+    fn crates_path(&self) -> PathBuf {
+        self.path.join("crates")
+    }
+    fn crate_path(&self, krate: &str) -> PathBuf {
+        self.path.join("crates").join(krate)
+    }
+
+    /// Path of a version's archive, and the counters to open it with.
+    fn crate_zup_path(&self, krate: &str, version: &str) -> (PathBuf, Arc<Counters>) {
+        let zup_path = self.crate_path(krate).join(format!("{}.zup", version));
+        // Only label metrics with crates that exist, so made-up URLs can't add series.
+        let counters = match self.catalog().versions(krate) {
+            Some(_) => self.metrics.zup_counters(krate),
+            None => Default::default(),
+        };
+        (zup_path, counters)
+    }
+
+    fn crate_zup(&self, krate: &str, version: &str) -> io::Result<Arc<Reader>> {
+        let (zup_path, counters) = self.crate_zup_path(krate, version);
+        self.readers.get(&zup_path, counters)
+    }
+
+    fn catalog(&self) -> Arc<Catalog> {
+        self.catalog.read().unwrap().clone()
+    }
+
+    /// Rescan the webroot and swap in the new catalog.
+    async fn rescan(&self) -> anyhow::Result<()> {
+        let prev = self.catalog();
+        let crates_path = self.crates_path();
+        let (catalog, prev) =
+            tokio::task::spawn_blocking(move || (Catalog::scan(&crates_path, &prev), prev)).await?;
+        let catalog = catalog?;
+        if catalog.differs(&prev) {
+            // Items were indexed per version, so only versions that went away are stale,
+            // but rebuilding is cheap enough to not bother tracking that.
+            let stats = catalog.stats();
+            self.search.reset(stats.crates);
+            info!(
+                "catalog updated: {} crates, {} versions, {} broken archives",
+                stats.crates,
+                stats.versions,
+                stats.broken.len()
+            );
+        }
+        *self.catalog.write().unwrap() = Arc::new(catalog);
+        Ok(())
+    }
+
+    fn list_crates(&self) -> io::Result<Vec<String>> {
+        Ok(self.catalog().crates().map(|s| s.to_string()).collect())
+    }
+
+    fn list_versions(&self, krate: &str) -> io::Result<Vec<String>> {
+        match self.catalog().versions(krate) {
+            Some(versions) => Ok(versions.iter().map(|v| v.name.clone()).collect()),
+            None => Err(ErrorKind::NotFound.into()),
+        }
+    }
+
+    /// Flavors of a version. Versions added since the last rescan are read from their archive.
+    fn list_flavors(&self, krate: &str, version: &str) -> io::Result<Vec<String>> {
+        match self.catalog().version(krate, version) {
+            Some(v) => Ok(v.flavors.clone()),
+            None => catalog::flavors(&*self.crate_zup(krate, version)?),
+        }
+    }
+
+    fn resp_400(&self, msg: &str) -> anyhow::Result<Response<Body>> {
+        let mut r = Response::new(full(format!("400 Bad Request: {}", msg)));
+        *r.status_mut() = StatusCode::BAD_REQUEST;
+        Ok(r)
+    }
+
+    /// Render an error page from the `{status}.html` template, or plain text if that fails.
+    fn error_page(&self, status: StatusCode, mut context: Context) -> Response<Body> {
+        if !context.contains_key("crates") {
+            context.insert("crates", &self.list_crates().unwrap_or_default());
+        }
+        if !context.contains_key("suggestions") {
+            context.insert("suggestions", &Vec::<Suggestion>::new());
+        }
+        let template = format!("{}.html", status.as_u16());
+        let mut r = match self.templates.render(&template, &context) {
+            Ok(html) => {
+                let mut r = Response::new(full(html));
+                r.headers_mut()
+                    .insert("Content-Type", HeaderValue::from_static("text/html"));
+                r
+            }
+            Err(e) => {
+                log::error!("rendering {} failed: {:?}", template, e);
+                Response::new(full(status.to_string()))
+            }
+        };
+        *r.status_mut() = status;
+        r
+    }
+
+    fn resp_404(&self) -> anyhow::Result<Response<Body>> {
+        Ok(self.error_page(StatusCode::NOT_FOUND, Context::new()))
+    }
+
+    /// 404 for a page of a crate's docs, with the nav bar and links to where the page does exist.
+    async fn resp_404_page(
+        &self,
+        krate: &str,
+        version: &str,
+        flavor: &str,
+        path: &[&str],
+    ) -> anyhow::Result<Response<Body>> {
+        let catalog = self.catalog();
+        let Some(latest) = catalog.latest(krate) else {
+            return self.resp_404();
+        };
+        // Versions that don't exist get the nav bar of the latest one.
+        let nav_version = match catalog.version(krate, version) {
+            Some(v) => v,
+            None => latest,
+        };
+        let Some(nav_flavor) = nav_version
+            .flavors
+            .iter()
+            .find(|f| *f == flavor)
+            .or(nav_version.flavors.first())
+        else {
+            return self.resp_404();
+        };
+
+        let mut context = self.nav_context(krate, &nav_version.name, nav_flavor)?;
+        context.insert(
+            "path",
+            &format!("/{}/{}/{}/{}", krate, version, flavor, path.join("/")),
+        );
+        context.insert(
+            "suggestions",
+            &self.suggestions(krate, version, flavor, path).await,
+        );
+        Ok(self.error_page(StatusCode::NOT_FOUND, context))
+    }
+
+    /// Where else a missing page might be: the same path in other flavors and versions, and
+    /// similarly named pages next to where it would have been.
+    ///
+    /// Anyone can request made-up pages, so the number of archive lookups is capped, and they
+    /// run on a blocking thread.
+    async fn suggestions(
+        &self,
+        krate: &str,
+        version: &str,
+        flavor: &str,
+        path: &[&str],
+    ) -> Vec<Suggestion> {
+        let catalog = self.catalog();
+        let Some(versions) = catalog.versions(krate) else {
+            return Vec::new();
+        };
+
+        // Where to look for the same path, as (version, flavor, archive). Archives are opened
+        // on the blocking thread, opening one that isn't cached reads its superblock.
+        let mut places = Vec::new();
+        let mut add = |version: &str, flavor: &str| {
+            let zup = self.crate_zup_path(krate, version);
+            places.push((version.to_string(), flavor.to_string(), zup));
+        };
+        let current = catalog.version(krate, version);
+        if let Some(v) = current {
+            for f in v
+                .flavors
+                .iter()
+                .filter(|f| *f != flavor)
+                .take(MAX_SUGGESTED_FLAVORS)
+            {
+                add(version, f);
+            }
+        }
+        for v in versions
+            .iter()
+            .filter(|v| v.name != version && v.flavors.iter().any(|f| f == flavor))
+            .take(MAX_SUGGESTED_VERSIONS)
+        {
+            add(&v.name, flavor);
+        }
+        let similar_in = current.map(|_| self.crate_zup_path(krate, version));
+        let readers = self.readers.clone();
+
+        let krate = krate.to_string();
+        let version = version.to_string();
+        let flavor = flavor.to_string();
+        let path: Vec<String> = path.iter().map(|s| s.to_string()).collect();
+        let res = tokio::task::spawn_blocking(move || {
+            let path: Vec<&str> = path.iter().map(|s| s.as_str()).collect();
+            let page = path.join("/");
+
+            let mut res = Vec::new();
+            for (v, f, (zup_path, counters)) in places {
+                let Ok(zup) = readers.get(&zup_path, counters) else {
+                    continue;
+                };
+                let mut zup_path = vec!["flavors", &f];
+                zup_path.extend_from_slice(&path);
+                if matches!(zup.open(&zup_path), Ok(Node::File(_))) {
+                    res.push(Suggestion {
+                        url: format!("/{}/{}/{}/{}", krate, v, f, page),
+                        text: format!("{} {} for {}", krate, v, f),
+                    });
+                }
+            }
+            if let Some((zup_path, counters)) = similar_in
+                && let Ok(zup) = readers.get(&zup_path, counters)
+            {
+                match suggest::similar_pages(&zup, &flavor, &path) {
+                    Ok(pages) => res.extend(pages.into_iter().map(|p| Suggestion {
+                        url: format!("/{}/{}/{}/{}", krate, version, flavor, p),
+                        text: p,
+                    })),
+                    Err(e) => log::warn!("looking for pages like {} failed: {:?}", page, e),
+                }
+            }
+            res.truncate(MAX_SUGGESTIONS);
+            res
+        })
+        .await;
+        match res {
+            Ok(res) => res,
+            Err(e) => {
+                log::error!("looking for suggestions failed: {:?}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn resp_500(&self, e: anyhow::Error) -> Response<Body> {
+        log::error!("{:?}", e);
+        self.error_page(StatusCode::INTERNAL_SERVER_ERROR, Context::new())
+    }
+
+    /// Context for `nav.html`.
+    fn nav_context(&self, krate: &str, version: &str, flavor: &str) -> io::Result<Context> {
+        let mut context = Context::new();
+        context.insert("crate", &krate);
+        context.insert("version", &version);
+        context.insert("flavor", &flavor);
+        let crates_list = self.list_crates()?;
+        // A crate added since the last rescan only has the version being viewed.
+        let versions_list = match self.list_versions(krate) {
+            Err(e) if e.kind() == ErrorKind::NotFound => vec![version.to_string()],
+            x => x?,
+        };
+        // Determine latest version: first non-git version
+        let latest_version = versions_list
+            .iter()
+            .find(|v| v.as_str() != "git")
+            .map(|s| s.as_str())
+            .unwrap_or(version);
+        context.insert("crates", &crates_list);
+        context.insert("versions", &versions_list);
+        context.insert("latest_version", &latest_version);
+        context.insert("flavors", &self.list_flavors(krate, version)?);
+        Ok(context)
+    }
+
+    fn resp_405(&self) -> anyhow::Result<Response<Body>> {
+        let mut r = Response::new(full("405 Method Not Allowed"));
+        *r.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+        r.headers_mut()
+            .insert("Allow", HeaderValue::from_static(ALLOW));
+        Ok(r)
+    }
+
+    fn resp_not_modified(&self, etag: &str) -> anyhow::Result<Response<Body>> {
+        let mut resp = Response::new(full(""));
+        *resp.status_mut() = StatusCode::NOT_MODIFIED;
+        resp.headers_mut().insert("ETag", etag.try_into()?);
+        Ok(resp)
+    }
+
+    fn resp_redirect(&self, path: &str) -> anyhow::Result<Response<Body>> {
+        let mut resp = Response::new(full("Redirect"));
+        *resp.status_mut() = StatusCode::FOUND;
+        let h = resp.headers_mut();
+        h.append("Location", path.try_into().unwrap());
+        Ok(resp)
+    }
+
+    async fn serve_static(
+        &self,
+        req: &Request<Incoming>,
+        pathh: &str,
+    ) -> anyhow::Result<Response<Body>> {
+        let path = self.path.join("static").join(pathh);
+        let data = match fs::read(path) {
+            Err(e) if e.kind() == ErrorKind::NotFound => return self.resp_404(),
+            x => x?,
+        };
+
+        let ext = extension(pathh);
+        let mime = mime_type(ext);
+
+        let etag = content_etag(&data);
+        if if_none_match(req, &etag) {
+            return self.resp_not_modified(&etag);
+        }
+        let ranged = range::requested(req, &etag, data.len() as u64);
+        let (offset, len) = ranged.window(data.len() as u64);
+        let data = Bytes::from(data).slice(offset as usize..(offset + len) as usize);
+
+        let mut resp = Response::new(full(data));
+        let h = resp.headers_mut();
+        h.insert("Content-Type", HeaderValue::from_static(mime));
+        h.insert("ETag", etag.try_into()?);
+        h.insert(
+            "Cache-Control",
+            HeaderValue::from_static("max-age=31536000"),
+        );
+        range::apply(&mut resp, &ranged);
+        Ok(resp)
+    }
+
+    fn resp_json(&self, value: &impl Serialize) -> anyhow::Result<Response<Body>> {
+        let json = serde_json::to_string(value)?;
+        let mut resp = Response::new(full(json));
+        resp.headers_mut()
+            .insert("Content-Type", HeaderValue::from_static("application/json"));
+        Ok(resp)
+    }
+
+    fn api_info(&self, krate: &str, version: &str) -> anyhow::Result<Response<Body>> {
+        let catalog = self.catalog();
+        let Some(v) = catalog.version(krate, version) else {
+            return self.resp_404();
+        };
+        let zup = self.crate_zup(krate, version)?;
+        let info: manifest::DocserverInfo =
+            serde_json::from_slice(&zup.read(&["info.json"])?).context("bad info.json")?;
+        let manifest: manifest::Manifest =
+            toml::from_slice(&zup.read(&["Cargo.toml"])?).context("bad Cargo.toml")?;
+        // Archives from before the build time was recorded have to make do with their mtime.
+        let build_time = info.build_time.or_else(|| {
+            v.mtime
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+        });
+
+        self.resp_json(&serde_json::json!({
+            "crate": krate,
+            "version": version,
+            "git_commit": info.git_commit.trim(),
+            "archive_size": v.len,
+            "build_time": build_time,
+            "flavors": v.flavors,
+            "embassy_docs": manifest.package.metadata.embassy_docs,
+        }))
+    }
+
+    /// Whether the server can actually serve docs, with details on what's wrong if not.
+    fn readyz(&self) -> anyhow::Result<Response<Body>> {
+        let mut checks = BTreeMap::new();
+
+        let webroot = fs::read_dir(self.crates_path())
+            .map(|_| ())
+            .with_context(|| format!("can't read {}", self.crates_path().display()));
+        checks.insert("webroot", webroot);
+        checks.insert("templates", self.templates.check());
+
+        // One good archive is enough, broken ones are reported in /api/stats.
+        let catalog = self.catalog();
+        let mut archives = Err(anyhow::anyhow!("no crate archives in the webroot"));
+        for krate in catalog.crates() {
+            let Some(version) = catalog.latest(krate) else {
+                continue;
+            };
+            let path = self.crate_path(krate).join(format!("{}.zup", version.name));
+            match Reader::new(&path) {
+                Ok(_) => {
+                    archives = Ok(());
+                    break;
+                }
+                Err(e) => archives = Err(anyhow::anyhow!("{}: {}", path.display(), e)),
+            }
+        }
+        checks.insert("archives", archives);
+
+        let ready = checks.values().all(|c| c.is_ok());
+        let checks: BTreeMap<_, _> = checks
+            .into_iter()
+            .map(|(name, c)| match c {
+                Ok(()) => (name, "ok".to_string()),
+                Err(e) => (name, format!("{:#}", e)),
+            })
+            .collect();
+        let mut resp = self.resp_json(&serde_json::json!({
+            "status": if ready { "ok" } else { "unavailable" },
+            "checks": checks,
+        }))?;
+        if !ready {
+            *resp.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        }
+        Ok(resp)
+    }
+
+    async fn api_search(&self, req: &Request<Incoming>) -> anyhow::Result<Response<Body>> {
+        let params = query_params(req);
+        let Some(q) = params
+            .get("q")
+            .map(|q| q.trim().to_lowercase())
+            .filter(|q| !q.is_empty())
+        else {
+            return self.resp_400("missing `q`");
+        };
+        let limit = params
+            .get("limit")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(50usize)
+            .clamp(1, 200);
+        let flavor = params.get("flavor");
+
+        // Search the latest release of every crate, in the requested flavor where the crate has it.
+        let catalog = self.catalog();
+        let mut sources = Vec::new();
+        for krate in catalog.crates() {
+            let versions = catalog.versions(krate).unwrap_or_default();
+            let Some(v) = versions
+                .iter()
+                .find(|v| v.name != "git")
+                .or(versions.first())
+            else {
+                continue;
+            };
+            let Some(flavor) = flavor
+                .filter(|f| v.flavors.contains(f))
+                .or(v.flavors.first())
+            else {
+                continue;
+            };
+            let items = match self.crate_zup(krate, &v.name) {
+                Ok(zup) => self.search.items(zup, krate, &v.name, flavor).await,
+                Err(e) => Err(e),
+            };
+            let items = match items {
+                Ok(items) => items,
+                Err(e) => {
+                    log::warn!("indexing {} {} failed: {}", krate, v.name, e);
+                    continue;
+                }
+            };
+            sources.push((krate, &v.name, flavor, items));
+        }
+
+        let mut matches = Vec::new();
+        for (i, (krate, _, _, items)) in sources.iter().enumerate() {
+            for (j, item) in items.iter().enumerate() {
+                if let Some(score) = search::score(item, krate, &q) {
+                    matches.push((score, i, j));
+                }
+            }
+        }
+        matches.sort_by(|a, b| {
+            let name = |(_, i, j): &(u32, usize, usize)| &sources[*i].3[*j].name;
+            a.0.cmp(&b.0)
+                .then_with(|| name(a).cmp(name(b)))
+                .then_with(|| sources[a.1].0.cmp(sources[b.1].0))
+        });
+
+        let hits: Vec<_> = matches
+            .into_iter()
+            .take(limit)
+            .map(|(_, i, j)| {
+                let (krate, version, flavor, items) = &sources[i];
+                let item = &items[j];
+                Hit {
+                    krate: krate.to_string(),
+                    version: version.to_string(),
+                    flavor: flavor.to_string(),
+                    name: item.name.clone(),
+                    kind: item.kind,
+                    path: search::rust_path(krate, item),
+                    url: format!("/{}/{}/{}/{}", krate, version, flavor, item.page),
+                }
+            })
+            .collect();
+
+        self.resp_json(&hits)
+    }
+
+    fn cookies(&self, req: &Request<Incoming>) -> HashMap<String, String> {
+        // Parse cookies
+        let mut cookies = HashMap::new();
+        if let Some(h) = req.headers().get("Cookie") {
+            for item in h.to_str().unwrap().split(';') {
+                if let Some((k, v)) = item.trim().split_once('=') {
+                    cookies.insert(k.to_string(), v.to_string());
+                }
+            }
+        }
+        cookies
+    }
+
+    async fn guess_redirect(
+        &self,
+        req: &Request<Incoming>,
+        mut krate: Option<&str>,
+        mut version: Option<&str>,
+    ) -> anyhow::Result<Response<Body>> {
+        let cookies = self.cookies(req);
+        let catalog = self.catalog();
+
+        // Crate
+        if krate.is_none() {
+            krate = cookies.get("crate").map(|s| s.as_str());
+        }
+        let mut krate = krate.unwrap_or("embassy-executor");
+        if catalog.versions(krate).is_none() {
+            krate = "embassy-executor";
+        }
+
+        // Version, defaulting to the latest non-git version
+        let Some(latest) = catalog.latest(krate) else {
+            return self.resp_404();
+        };
+        if version.is_none() {
+            version = cookies
+                .get(&format!("crate-{}-version", krate))
+                .map(|s| s.as_str());
+        }
+        // `latest` and semver requirements are kept in the URL, flavors come from what they resolve to.
+        let mut version = version.unwrap_or(&latest.name);
+        let resolved = match catalog.resolve(krate, &percent_decode(version)) {
+            Some(v) => v,
+            None => {
+                version = &latest.name;
+                latest
+            }
+        };
+
+        // Flavor
+        let flavors = &resolved.flavors;
+        let Some(default_flavor) = flavors.first() else {
+            return self.resp_404();
+        };
+        let flavor = cookies
+            .get(&format!("crate-{}-flavor", krate))
+            .map(|s| s.as_str());
+        let mut flavor = flavor.unwrap_or(default_flavor);
+        if !flavors.iter().any(|s| s == flavor) {
+            flavor = default_flavor;
+        }
+
+        self.resp_redirect(&format!("/{}/{}/{}/index.html", krate, version, flavor))
+    }
+
+    /// Redirect to the docs of a Rust path like `embassy_stm32::gpio::Output`, in the latest
+    /// version of the crate and the flavor the user last picked.
+    async fn go_to(&self, req: &Request<Incoming>, item: &str) -> anyhow::Result<Response<Body>> {
+        let item = percent_decode(item);
+        let mut segments: Vec<&str> = item.split("::").filter(|s| !s.is_empty()).collect();
+        if segments.is_empty() {
+            return self.resp_400("missing item path");
+        }
+        let head = segments.remove(0);
+
+        let catalog = self.catalog();
+        let Some((krate, version)) = catalog
+            .crates()
+            .find(|k| k.replace('-', "_") == head)
+            .and_then(|k| Some((k, catalog.latest(k)?)))
+        else {
+            return self.resp_404();
+        };
+
+        let cookies = self.cookies(req);
+        let Some(flavor) = cookies
+            .get(&format!("crate-{}-flavor", krate))
+            .filter(|f| version.flavors.contains(f))
+            .or(version.flavors.first())
+        else {
+            return self.resp_404();
+        };
+
+        let zup = self.crate_zup(krate, &version.name)?;
+        let mut pages = goto::resolve(&zup, flavor, &segments)?;
+        if pages.is_empty() {
+            // Re-exports have their page where the item is defined, so look it up by name.
+            let name = segments.last().unwrap();
+            let items = self
+                .search
+                .items(zup.clone(), krate, &version.name, flavor)
+                .await?;
+            pages = items
+                .iter()
+                .filter(|i| i.parent.is_none() && i.name == *name)
+                .map(|i| i.page.clone())
+                .collect();
+        }
+
+        let urls: Vec<_> = pages
+            .iter()
+            .map(|p| format!("/{}/{}/{}/{}", krate, version.name, flavor, p))
+            .collect();
+        match &urls[..] {
+            [] => self.resp_404(),
+            [url] => self.resp_redirect(url),
+            _ => {
+                let mut html = format!(
+                    "<!DOCTYPE html>\n<title>{}</title>\n<p>Multiple items match <code>{}</code>:</p>\n<ul>\n",
+                    html_escape(&item),
+                    html_escape(&item),
+                );
+                for url in &urls {
+                    let url = html_escape(url);
+                    html.push_str(&format!("<li><a href=\"{url}\">{url}</a></li>\n"));
+                }
+                html.push_str("</ul>\n");
+                let mut resp = Response::new(full(html));
+                *resp.status_mut() = StatusCode::MULTIPLE_CHOICES;
+                resp.headers_mut().insert(
+                    "Content-Type",
+                    HeaderValue::from_static("text/html; charset=utf-8"),
+                );
+                Ok(resp)
+            }
+        }
+    }
+
+    async fn serve_inner(&self, req: Request<Incoming>) -> anyhow::Result<Response<Body>> {
+        match *req.method() {
+            Method::GET | Method::HEAD => {}
+            Method::OPTIONS => {
+                let mut resp = Response::new(full(""));
+                *resp.status_mut() = StatusCode::NO_CONTENT;
+                resp.headers_mut()
+                    .insert("Allow", HeaderValue::from_static(ALLOW));
+                return Ok(resp);
+            }
+            _ => return self.resp_405(),
+        }
+
+        let raw_path = req.uri().path();
+        let mut path = Vec::new();
+        for x in raw_path.split('/') {
+            match x {
+                "" | "." => {}
+                ".." => {
+                    path.pop();
+                }
+                _ => path.push(x),
+            }
+        }
+
+        match path[..] {
+            // Serve static file
+            ["static", ref path @ ..] => self.serve_static(&req, &path.join("/")).await,
+
+            // JSON API endpoints
+            ["api", "crates"] => {
+                let crates = self.list_crates()?;
+                let crates: Vec<_> = crates
+                    .iter()
+                    .map(|name| serde_json::json!({"name": name}))
+                    .collect();
+                self.resp_json(&crates)
+            }
+            ["api", "crates", krate, "versions"] => {
+                let versions = match self.list_versions(krate) {
+                    Ok(v) => v,
+                    Err(e) if e.kind() == ErrorKind::NotFound => return self.resp_404(),
+                    Err(e) => return Err(e.into()),
+                };
+                let versions: Vec<_> = versions
+                    .iter()
+                    .map(|version| serde_json::json!({"version": version}))
+                    .collect();
+                self.resp_json(&versions)
+            }
+            ["api", "crates", krate, "latest"] => match self.catalog().latest(krate) {
+                Some(v) => self.resp_json(&serde_json::json!({"version": v.name})),
+                None => self.resp_404(),
+            },
+            ["api", "crates", krate, version, "flavors"] => {
+                let flavors = match self.list_flavors(krate, version) {
+                    Ok(v) => v,
+                    Err(e) if e.kind() == ErrorKind::NotFound => return self.resp_404(),
+                    Err(e) => return Err(e.into()),
+                };
+                let flavors: Vec<_> = flavors
+                    .iter()
+                    .map(|flavor| serde_json::json!({"name": flavor}))
+                    .collect();
+                self.resp_json(&flavors)
+            }
+            ["api", "crates", krate, version, "info"] => self.api_info(krate, version),
+            ["api", "search"] => self.api_search(&req).await,
+            ["api", "stats"] => {
+                let mut stats = serde_json::to_value(self.readers.stats())?;
+                stats["catalog"] = serde_json::to_value(self.catalog().stats())?;
+                self.resp_json(&stats)
+            }
+
+            ["healthz"] => self.resp_json(&serde_json::json!({"status": "ok"})),
+            ["readyz"] => self.readyz(),
+
+            ["metrics"] => {
+                let text = self
+                    .metrics
+                    .render(&self.readers.stats(), self.catalog().stats().broken.len());
+                let mut resp = Response::new(full(text));
+                resp.headers_mut().insert(
+                    "Content-Type",
+                    HeaderValue::from_static("text/plain; version=0.0.4"),
+                );
+                Ok(resp)
+            }
+
+            ["go", ref item @ ..] => self.go_to(&req, &item.join("::")).await,
+
+            [] => self.guess_redirect(&req, None, None).await,
+            [krate] => self.guess_redirect(&req, Some(krate), None).await,
+            [krate, version] => self.guess_redirect(&req, Some(krate), Some(version)).await,
+
+            // Get file from crate version+flavor
+            [krate, version, flavor, ..] => {
+                // Versions not in the catalog yet are still tried, they may have been added
+                // since the last rescan.
+                let resolved = match self.catalog().resolve(krate, &percent_decode(version)) {
+                    Some(v) => v.name.clone(),
+                    None => version.to_string(),
+                };
+                let mut resp = self
+                    .serve_crate_file(&req, krate, &resolved, flavor, &path)
+                    .await?;
+                if resolved != version && resp.status().is_success() {
+                    let canonical = format!("/{}/{}/{}", krate, resolved, path[2..].join("/"));
+                    resp.headers_mut().insert(
+                        "Link",
+                        format!("<{}>; rel=\"canonical\"", canonical).try_into()?,
+                    );
+                }
+                resp.extensions_mut().insert(Resolved {
+                    krate: krate.to_string(),
+                    version: resolved,
+                    flavor: flavor.to_string(),
+                });
+                Ok(resp)
+            }
+        }
+    }
+
+    /// Serve `path`, which starts with `krate`, `version` and `flavor`, from the crate's archive.
+    ///
+    /// `version` is the resolved version, `path[1]` the one from the URL. Redirects keep the
+    /// latter, so `latest` links stay `latest`.
+    async fn serve_crate_file(
+        &self,
+        req: &Request<Incoming>,
+        krate: &str,
+        version: &str,
+        flavor: &str,
+        path: &[&str],
+    ) -> anyhow::Result<Response<Body>> {
+        let zup = match self.crate_zup(krate, version) {
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return self.resp_404_page(krate, version, flavor, &path[3..]).await;
+            }
+            x => x?,
+        };
+
+        // redirect remove extra crate name in path.
+        if path.len() > 3 && path[3] == krate.replace('-', "_") {
+            return self.resp_redirect(&format!(
+                "/{}/{}/{}/{}",
+                krate,
+                path[1],
+                flavor,
+                path[4..].join("/")
+            ));
+        }
+
+        let mut zup_path = vec!["flavors"];
+        zup_path.extend_from_slice(&path[2..]);
+        let file = match zup.open(&zup_path) {
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                // check if it's due to incorrect flavor.
+                if zup.open(&["flavors", flavor]).is_ok() {
+                    // if flavor exists, path is wrong, so do 404.
+                    return self.resp_404_page(krate, version, flavor, &path[3..]).await;
+                } else {
+                    // flavor doesn't exist, redirect to the default flavor.
+                    let cookies = self.cookies(req);
+
+                    let flavors = self.list_flavors(krate, version)?;
+                    let Some(flavor) = cookies
+                        .get(&format!("crate-{}-flavor", krate))
+                        .filter(|f| flavors.contains(f))
+                        .or(flavors.first())
+                    else {
+                        return self.resp_404();
+                    };
+
+                    return self.resp_redirect(&format!(
+                        "/{}/{}/{}/{}",
+                        krate,
+                        path[1],
+                        flavor,
+                        path[3..].join("/")
+                    ));
+                }
+            }
+            Ok(Node::Directory(_)) => {
+                return self.resp_redirect(&format!(
+                    "/{}/{}/{}/{}index.html",
+                    krate,
+                    path[1],
+                    flavor,
+                    path[3..].iter().fold(String::new(), |mut s, p| {
+                        s.push_str(p);
+                        s.push('/');
+                        s
+                    })
+                ));
+            }
+            Ok(Node::File(f)) => f,
+            Err(e) => return Err(e.into()),
+        };
+
+        let ext = extension(path[path.len() - 1]);
+        let mime = mime_type(ext);
+
+        // Non-HTML files are sent as stored, so a dictless zstd frame can be forwarded as-is.
+        let stored_zstd = ext != "html" && file.node().flags & layout::FLAG_NO_DICT != 0;
+        let size = file.size()?;
+        // Ranges are served from the uncompressed content.
+        let encoding = match self.compress_responses && !req.headers().contains_key("Range") {
+            true => encoding::negotiate(req, stored_zstd),
+            false => Encoding::Identity,
+        };
+        let encoding = match encoding {
+            // Streamed bodies aren't compressed on the fly.
+            Encoding::Brotli | Encoding::Gzip
+                if !encoding::compressible(mime) || (ext != "html" && size > STREAM_THRESHOLD) =>
+            {
+                Encoding::Identity
+            }
+            e => e,
+        };
+
+        // HTML gets rewritten, so its ETag has to come from the final content.
+        let node_etag = match ext {
+            "html" => None,
+            _ => Some(encoding.etag(&node_etag(&zup, file.node())?)),
+        };
+        if let Some(etag) = &node_etag
+            && if_none_match(req, etag)
+        {
+            return self.resp_not_modified(etag);
+        }
+
+        let (body, etag, ranged) = if let Some(etag) = &node_etag
+            && encoding == Encoding::Zstd
+            && stored_zstd
+        {
+            let zup_path = zup_path.iter().map(|s| s.to_string()).collect();
+            let body = body::stream_zstd_frame(zup.clone(), zup_path, file.node().range.len);
+            (body, etag.clone(), Ranged::Full)
+        } else if let Some(etag) = &node_etag
+            && size > STREAM_THRESHOLD
+        {
+            let ranged = range::requested(req, etag, size);
+            let body = match ranged {
+                // Nothing to send, don't start decompressing.
+                Ranged::Unsatisfiable { .. } => full(""),
+                _ => {
+                    let (offset, len) = ranged.window(size);
+                    let zup_path = zup_path.iter().map(|s| s.to_string()).collect();
+                    body::stream_file(zup.clone(), zup_path, offset, len)
+                }
+            };
+            (body, etag.clone(), ranged)
+        } else {
+            let mut data = file.read()?;
+            if ext == "html" {
+                let manifest = zup.read(&["Cargo.toml"]).unwrap();
+                let manifest: manifest::Manifest = toml::from_slice(&manifest).unwrap();
+                let meta = &manifest.package.metadata.embassy_docs;
+
+                let info = zup.read(&["info.json"]).unwrap();
+                let info: manifest::DocserverInfo = serde_json::from_slice(&info).unwrap();
+
+                let srclink_base = if version == "git" {
+                    meta.src_base_git
+                        .replace("$COMMIT", &info.git_commit)
+                        .to_string()
+                } else {
+                    meta.src_base.replace("$VERSION", version).to_string()
+                };
+
+                let re = ByteRegex::new("(src|href)=\"([^\"]+)\"").unwrap();
+                data = re
+                    .replace_all(&data, |c: &Captures| {
+                        let attr = c.get(1).unwrap().as_bytes();
+                        let mut link = c.get(2).unwrap().as_bytes().to_vec();
+
+                        if link.starts_with(b"/__DOCSERVER_SRCLINK/") {
+                            let link_path = std::str::from_utf8(&link[21..]).unwrap();
+                            let i = link_path.find('#').unwrap();
+                            let link_fragment = link_path[i + 1..].replace('-', "-L");
+                            let link_path = link_path[..i].replace(".html", "");
+                            link =
+                                format!("{}{}#L{}", srclink_base, link_path, link_fragment).into();
+                        }
+
+                        if link.starts_with(b"/__DOCSERVER_DEPLINK/") {
+                            let link_path = std::str::from_utf8(&link[21..]).unwrap();
+                            let (krate, link_path) = link_path.split_once('/').unwrap();
+                            let (_, link_path) = link_path.split_once('/').unwrap();
+
+                            link = format!("/{krate}/git/{flavor}/{link_path}").into();
+                        }
+
+                        let mut res = Vec::new();
+                        res.extend_from_slice(attr);
+                        res.extend_from_slice(b"=");
+                        res.extend_from_slice(b"\"");
+                        res.extend_from_slice(&link);
+                        res.extend_from_slice(b"\"");
+                        res
+                    })
+                    .into_owned();
+                let re_head = ByteRegex::new("</head>").unwrap();
+                let re_body = ByteRegex::new("<body class=\"([^\"]*)\">").unwrap();
+                if let (Some(head), Some(body)) = (re_head.find(&data), re_body.captures(&data)) {
+                    let context = self.nav_context(krate, version, flavor)?;
+
+                    let rendered_head = self.templates.render("head.html", &context).unwrap();
+                    let rendered_nav = self.templates.render("nav.html", &context).unwrap();
+
+                    let m = body.get(0).unwrap();
+                    let mut data2 = Vec::new();
+                    data2.extend_from_slice(&data[..head.start()]);
+                    data2.extend_from_slice(rendered_head.as_bytes());
+                    data2.extend_from_slice(&data[head.start()..m.start()]);
+                    data2.extend_from_slice(b"<body>");
+                    data2.extend_from_slice(rendered_nav.as_bytes());
+                    data2.extend_from_slice(b"<div class=\"body-wrapper ");
+                    data2.extend_from_slice(&body[1]);
+                    data2.extend_from_slice(b"\">");
+                    data2.extend_from_slice(&data[m.end()..]);
+                    data = data2;
+                }
+            }
+            let etag = node_etag.unwrap_or_else(|| encoding.etag(&content_etag(&data)));
+            if encoding != Encoding::Identity {
+                // Brotli on a large page, like a source view, would hold up this worker thread.
+                data = tokio::task::spawn_blocking(move || encoding::compress(encoding, &data))
+                    .await??;
+            }
+            let ranged = match encoding {
+                Encoding::Identity => range::requested(req, &etag, data.len() as u64),
+                _ => Ranged::Full,
+            };
+            let (offset, len) = ranged.window(data.len() as u64);
+            let data = Bytes::from(data).slice(offset as usize..(offset + len) as usize);
+            (full(data), etag, ranged)
+        };
+        if if_none_match(req, &etag) {
+            return self.resp_not_modified(&etag);
+        }
+
+        let mut resp = Response::new(body);
+        let h = resp.headers_mut();
+        h.append("Content-Type", mime.try_into().unwrap());
+        h.insert("ETag", etag.try_into().unwrap());
+        if let Some(name) = encoding.name() {
+            h.insert("Content-Encoding", HeaderValue::from_static(name));
+        }
+        if self.compress_responses {
+            h.insert("Vary", HeaderValue::from_static("Accept-Encoding"));
+        }
+
+        let mut set_cookie = |k, v| {
+            h.append(
+                "Set-Cookie",
+                format!("{}={}; Path=/; Max-Age=31536000", k, v)
+                    .try_into()
+                    .unwrap(),
+            );
+        };
+
+        let cookie_version = format!("crate-{}-version", krate);
+        let cookie_flavor = format!("crate-{}-flavor", krate);
+        set_cookie("crate", &krate);
+        set_cookie(&cookie_version, &version);
+        set_cookie(&cookie_flavor, &flavor);
+
+        range::apply(&mut resp, &ranged);
+        Ok(resp)
+    }
+
+    pub async fn serve(
+        &self,
+        req: Request<Incoming>,
+        client: Option<SocketAddr>,
+    ) -> Response<Body> {
+        let method = req.method().clone();
+        let uri = req.uri().clone();
+        let http_version = req.version();
+        let header = |name| {
+            req.headers()
+                .get(name)
+                .and_then(|h| h.to_str().ok())
+                .map(|s| s.to_string())
+        };
+        let referer = header("Referer");
+        let user_agent = header("User-Agent");
+        let id = access_log::request_id(header("X-Request-Id").as_deref());
+        let start = Instant::now();
+
+        let mut resp = match self.serve_inner(req).await {
+            Ok(resp) => resp,
+            Err(e) => self.resp_500(e),
+        };
+        // HEAD gets the same headers as GET, with the length of the body it would have had.
+        if method == Method::HEAD {
+            if let Some(len) = resp.body().size_hint().exact() {
+                resp.headers_mut().insert("Content-Length", len.into());
+            }
+            *resp.body_mut() = full("");
+        }
+        let duration = start.elapsed();
+        let bytes = resp.body().size_hint().exact();
+
+        self.access_log.write(&Entry {
+            id: &id,
+            client,
+            method: &method,
+            uri: &uri,
+            http_version,
+            referer: referer.as_deref(),
+            user_agent: user_agent.as_deref(),
+            status: resp.status(),
+            bytes,
+            duration,
+            resolved: resp.extensions().get::<Resolved>(),
+        });
+        self.metrics.record(
+            metrics::route_kind(uri.path()),
+            resp.status().as_u16(),
+            duration,
+            bytes,
+        );
+        if let Ok(id) = id.try_into() {
+            resp.headers_mut().insert("X-Request-Id", id);
+        }
+        resp
+    }
+}
+
+#[derive(Parser)]
+pub struct ServeArgs {
+    /// Path to the webroot containing crates and static files
+    #[clap(long, env = "DOCSERVER_WEBROOT")]
+    pub webroot: Option<PathBuf>,
+
+    /// Address to listen on, as `host:port` or `unix:/path/to/socket`. Can be given multiple times
+    #[clap(long, default_value = "0.0.0.0:3000")]
+    pub listen: Vec<ListenAddr>,
+
+    /// PEM certificate chain to serve HTTPS with
+    #[clap(long, requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key for `--tls-cert`
+    #[clap(long, requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Directory with head.html, nav.html, 404.html and 500.html templates. Missing ones, or all
+    /// of them without this, are the ones built into the binary
+    #[clap(long, env = "DOCSERVER_TEMPLATES")]
+    pub templates: Option<PathBuf>,
+
+    /// Re-read templates from `--templates` when they change, for working on them
+    #[clap(long, requires = "templates")]
+    pub reload_templates: bool,
+
+    /// How to log requests
+    #[clap(long, value_enum, default_value_t)]
+    pub access_log: AccessLogFormat,
+
+    /// Memory budget for cached decompressed archive nodes, in megabytes
+    #[clap(long, default_value = "256")]
+    pub cache_size: usize,
+
+    /// Maximum number of archives kept open at once
+    #[clap(long, default_value = "64")]
+    pub max_open_archives: usize,
+
+    /// Compress responses for clients that accept it, forwarding stored zstd frames where possible
+    #[clap(long)]
+    pub compress_responses: bool,
+
+    /// How often to rescan the webroot for added or removed archives, in seconds
+    #[clap(long, default_value = "10", value_parser = clap::value_parser!(u64).range(1..))]
+    pub rescan_interval: u64,
+
+    /// On SIGTERM/SIGINT, how long to wait for in-flight connections to finish, in seconds
+    #[clap(long, default_value = "30")]
+    pub shutdown_timeout: u64,
+}
+
+/// Resolves when the process is asked to terminate.
+async fn shutdown_signal() -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut term = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = term.recv() => Ok(()),
+            res = tokio::signal::ctrl_c() => res,
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
+}
+
+async fn accept_loop(
+    thing: &'static Thing,
+    listener: Listener,
+    tls: Option<TlsAcceptor>,
+    graceful: Arc<GracefulShutdown>,
+    mut stop: watch::Receiver<()>,
+) {
+    loop {
+        let res = tokio::select! {
+            res = listener.accept() => res,
+            _ = stop.changed() => return,
+        };
+        let (io, client) = match res {
+            Ok(conn) => conn,
+            Err(e) => {
+                log::error!("accepting connection failed: {:?}", e);
+                tokio::select! {
+                    _ = tokio::time::sleep(ACCEPT_BACKOFF) => continue,
+                    _ = stop.changed() => return,
+                }
+            }
+        };
+        let tls = tls.clone();
+        let watcher = graceful.watcher();
+
+        tokio::task::spawn(serve_connection(thing, io, client, tls, watcher));
+    }
+}
+
+async fn serve_connection(
+    thing: &'static Thing,
+    io: Box<dyn Io>,
+    client: Option<SocketAddr>,
+    tls: Option<TlsAcceptor>,
+    watcher: Watcher,
+) {
+    let io: Box<dyn Io> = match tls {
+        Some(tls) => match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, tls.accept(io)).await {
+            Ok(Ok(io)) => Box::new(io),
+            Ok(Err(err)) => {
+                log::debug!("TLS handshake failed: {:?}", err);
+                return;
+            }
+            Err(_) => {
+                log::debug!("TLS handshake timed out");
+                return;
+            }
+        },
+        None => io,
+    };
+
+    let builder = auto::Builder::new(TokioExecutor::new());
+    let conn = builder.serve_connection(
+        TokioIo::new(io),
+        service_fn(move |req| async move {
+            Result::<_, Infallible>::Ok(thing.serve(req, client).await)
+        }),
+    );
+    if let Err(err) = watcher.watch(conn).await {
+        log::warn!("Error serving connection: {:?}", err);
+    }
+}
+
+pub async fn run(args: ServeArgs) -> anyhow::Result<()> {
+    let templates = match &args.templates {
+        Some(dir) => Templates::load(dir, args.reload_templates)?,
+        None => Templates::embedded()?,
+    };
+
+    let webroot: PathBuf = args.webroot.unwrap_or_else(|| {
+        env::var_os("DOCSERVER_WEBROOT")
+            .expect("Missing DOCSERVER_WEBROOT environment variable or --webroot argument")
+            .into()
+    });
+
+    let tls = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => Some(listen::tls_acceptor(cert, key)?),
+        _ => None,
+    };
+    let scheme = if tls.is_some() { "https" } else { "http" };
+
+    let nodes = Arc::new(NodeCache::new(args.cache_size * 1_000_000));
+    let thing = Thing {
+        path: webroot,
+        templates,
+        readers: Arc::new(ReaderCache::new(args.max_open_archives, nodes)),
+        catalog: RwLock::default(),
+        metrics: Metrics::default(),
+        search: SearchIndex::default(),
+        access_log: args.access_log,
+        compress_responses: args.compress_responses,
+    };
+    let thing: &'static Thing = Box::leak(Box::new(thing));
+
+    if let Err(e) = thing.rescan().await {
+        log::error!("scanning webroot failed: {:?}", e);
+    }
+    let rescan_interval = Duration::from_secs(args.rescan_interval);
+    tokio::task::spawn(async move {
+        loop {
+            tokio::time::sleep(rescan_interval).await;
+            if let Err(e) = thing.rescan().await {
+                log::error!("scanning webroot failed: {:?}", e);
+            }
+        }
+    });
+
+    let graceful = Arc::new(GracefulShutdown::new());
+    let (stop_tx, stop_rx) = watch::channel(());
+
+    let mut tasks = JoinSet::new();
+    for addr in &args.listen {
+        let listener = Listener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to listen on {}", addr))?;
+        println!("Listening on {}://{}", scheme, addr);
+        tasks.spawn(accept_loop(
+            thing,
+            listener,
+            tls.clone(),
+            graceful.clone(),
+            stop_rx.clone(),
+        ));
+    }
+
+    shutdown_signal().await?;
+
+    // Stop accepting, then give open connections some time to finish their requests.
+    println!(
+        "Shutting down, waiting up to {}s for open connections",
+        args.shutdown_timeout
+    );
+    stop_tx.send(())?;
+    while let Some(res) = tasks.join_next().await {
+        res?;
+    }
+    for addr in &args.listen {
+        if let ListenAddr::Unix(path) = addr {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    let graceful = Arc::into_inner(graceful).expect("accept loops have stopped");
+    let timeout = Duration::from_secs(args.shutdown_timeout);
+    match tokio::time::timeout(timeout, graceful.shutdown()).await {
+        Ok(()) => println!("All connections closed"),
+        Err(_) => println!("Timed out waiting for connections, exiting anyway"),
+    }
+    Ok(())
+}
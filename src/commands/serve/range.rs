@@ -0,0 +1,143 @@
+use hyper::body::Incoming;
+use hyper::header::HeaderValue;
+use hyper::{Request, Response, StatusCode};
+
+use super::body::{Body, full};
+
+/// What part of a response of `total` bytes the client asked for with `Range`.
+#[derive(Debug, PartialEq)]
+pub enum Ranged {
+    Full,
+    /// Bytes `start..end`.
+    Partial {
+        start: u64,
+        end: u64,
+        total: u64,
+    },
+    Unsatisfiable {
+        total: u64,
+    },
+}
+
+impl Ranged {
+    /// Offset and length of the bytes to send.
+    pub fn window(&self, total: u64) -> (u64, u64) {
+        match *self {
+            Ranged::Partial { start, end, .. } => (start, end - start),
+            _ => (0, total),
+        }
+    }
+}
+
+/// Parse a `bytes=` range header value. Only single ranges are supported, anything else is
+/// answered with the full response, which the spec allows.
+fn parse(value: &str, total: u64) -> Ranged {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return Ranged::Full;
+    };
+    if spec.contains(',') {
+        return Ranged::Full;
+    }
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return Ranged::Full;
+    };
+    let (start, end) = match (first.parse::<u64>(), last.parse::<u64>()) {
+        // `bytes=100-199`, the end is inclusive.
+        (Ok(first), Ok(last)) if first <= last => (first, last.saturating_add(1).min(total)),
+        // `bytes=100-`
+        (Ok(first), Err(_)) if last.is_empty() => (first, total),
+        // `bytes=-100`, the last 100 bytes.
+        (Err(_), Ok(suffix)) if first.is_empty() => {
+            if suffix == 0 {
+                return Ranged::Unsatisfiable { total };
+            }
+            (total.saturating_sub(suffix), total)
+        }
+        _ => return Ranged::Full,
+    };
+    if start >= total || end <= start {
+        return Ranged::Unsatisfiable { total };
+    }
+    Ranged::Partial { start, end, total }
+}
+
+/// The range to send for a response of `total` bytes with the given ETag.
+///
+/// With `If-Range`, the range only applies if the client's copy is still current. We don't send
+/// `Last-Modified`, so dates never match.
+pub fn requested(req: &Request<Incoming>, etag: &str, total: u64) -> Ranged {
+    let Some(range) = req.headers().get("Range").and_then(|h| h.to_str().ok()) else {
+        return Ranged::Full;
+    };
+    if let Some(if_range) = req.headers().get("If-Range")
+        && if_range.as_bytes() != etag.as_bytes()
+    {
+        return Ranged::Full;
+    }
+    parse(range, total)
+}
+
+/// Turn a full response into a 206 or 416, according to `ranged`. The body must already be
+/// cut down to the range.
+pub fn apply(resp: &mut Response<Body>, ranged: &Ranged) {
+    let h = resp.headers_mut();
+    h.insert("Accept-Ranges", HeaderValue::from_static("bytes"));
+    match *ranged {
+        Ranged::Full => {}
+        Ranged::Partial { start, end, total } => {
+            let content_range = format!("bytes {}-{}/{}", start, end - 1, total);
+            h.insert("Content-Range", content_range.try_into().unwrap());
+            *resp.status_mut() = StatusCode::PARTIAL_CONTENT;
+        }
+        Ranged::Unsatisfiable { total } => {
+            let content_range = format!("bytes */{}", total);
+            h.insert("Content-Range", content_range.try_into().unwrap());
+            h.remove("Content-Type");
+            h.remove("Content-Encoding");
+            *resp.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+            *resp.body_mut() = full("");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partial(start: u64, end: u64) -> Ranged {
+        Ranged::Partial {
+            start,
+            end,
+            total: 1000,
+        }
+    }
+
+    #[test]
+    fn parse_ranges() {
+        let unsatisfiable = Ranged::Unsatisfiable { total: 1000 };
+        assert_eq!(parse("bytes=100-199", 1000), partial(100, 200));
+        assert_eq!(parse("bytes=900-2000", 1000), partial(900, 1000));
+        // Suffix
+        assert_eq!(parse("bytes=-100", 1000), partial(900, 1000));
+        assert_eq!(parse("bytes=-5000", 1000), partial(0, 1000));
+        assert_eq!(parse("bytes=-0", 1000), unsatisfiable);
+        // Open-ended
+        assert_eq!(parse("bytes=100-", 1000), partial(100, 1000));
+        assert_eq!(parse("bytes=0-", 1000), partial(0, 1000));
+        // Inverted
+        assert_eq!(parse("bytes=200-100", 1000), Ranged::Full);
+        // Overflowing
+        assert_eq!(
+            parse("bytes=0-18446744073709551615", 1000),
+            partial(0, 1000)
+        );
+        assert_eq!(parse("bytes=0-18446744073709551616", 1000), Ranged::Full);
+        // Out of range
+        assert_eq!(parse("bytes=1000-1100", 1000), unsatisfiable);
+        assert_eq!(parse("bytes=1000-", 1000), unsatisfiable);
+        assert_eq!(parse("bytes=-1", 0), Ranged::Unsatisfiable { total: 0 });
+        // Unsupported
+        assert_eq!(parse("bytes=0-1,5-6", 1000), Ranged::Full);
+        assert_eq!(parse("items=0-1", 1000), Ranged::Full);
+    }
+}
@@ -0,0 +1,657 @@
+//! Reading the search index rustdoc writes next to the docs, copied into each flavor by `build`.
+//!
+//! Rustdoc since nightly-2025-08 writes a `search.index` directory: a `root.js` describing
+//! columns like `name`, `entry` and `path`, whose rows are split over chunk files named by
+//! hash. Row `i` of every column is about the same item or path. Older rustdoc writes one
+//! `search-index.js`, a JSON object per crate. Both are decoded like rustdoc's own `search.js`
+//! and `stringdex.js` do.
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+
+use crate::common::zup::read::Reader;
+
+/// Item kinds by the number rustdoc uses for them in the index.
+const ITEM_TYPES: &[&str] = &[
+    "keyword",
+    "primitive",
+    "mod",
+    "externcrate",
+    "import",
+    "struct",
+    "enum",
+    "fn",
+    "type",
+    "static",
+    "trait",
+    "impl",
+    "tymethod",
+    "method",
+    "structfield",
+    "variant",
+    "macro",
+    "associatedtype",
+    "constant",
+    "associatedconstant",
+    "union",
+    "foreigntype",
+    "existential",
+    "attr",
+    "derive",
+    "traitalias",
+    "generic",
+    "attribute",
+];
+
+/// An item from the index, as rustdoc's search results show it.
+pub struct Entry {
+    pub name: String,
+    pub kind: &'static str,
+    /// Path of the module the item is in, starting with the crate name.
+    pub module: String,
+    /// Kind and name of the type, trait or variant a method, field or variant belongs to.
+    pub parent: Option<(&'static str, String)>,
+    /// Told apart same-named members of different impls, goes before the anchor.
+    pub disambiguator: Option<String>,
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Name of an item kind, `None` for kinds newer than this list.
+fn kind(ty: u64) -> Option<&'static str> {
+    ITEM_TYPES.get(ty as usize).copied()
+}
+
+/// Items of `krate` (with underscores) documented in `flavor`, from whichever index it has.
+pub fn read(zup: &Reader, flavor: &str, krate: &str) -> io::Result<Vec<Entry>> {
+    match zup.read(&["flavors", flavor, "search.index", "root.js"]) {
+        Ok(root) => Stringdex::new(zup, flavor, &root)?.entries(krate),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let js = zup.read(&["flavors", flavor, "search-index.js"])?;
+            legacy_entries(&js, krate)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// The argument of a call like `rd_("...")` that makes up a whole index file.
+fn call_arg<'a>(data: &'a [u8], func: &str) -> io::Result<&'a [u8]> {
+    let open = func.len() + 1;
+    let data = data.trim_ascii();
+    match data.strip_prefix(func.as_bytes()) {
+        Some(rest) if rest.len() >= 2 && rest[0] == b'(' => {
+            let close = rest.iter().rposition(|c| *c == b')').unwrap_or(0);
+            Ok(&data[open..func.len() + close])
+        }
+        _ => Err(invalid(format!("not a {} call", func))),
+    }
+}
+
+/// A number written as hex digits in the low nibble of each byte, the last one at or above 96
+/// (`` ` ``). Returns the number and the bytes after it.
+fn read_varint(data: &[u8]) -> io::Result<(u64, &[u8])> {
+    let mut n = 0u64;
+    for (i, c) in data.iter().enumerate() {
+        n = n
+            .checked_mul(16)
+            .ok_or_else(|| invalid("number too large"))?
+            | (*c & 0xf) as u64;
+        if *c >= 96 {
+            return Ok((n, &data[i + 1..]));
+        }
+    }
+    Err(invalid("truncated number"))
+}
+
+/// Decoding a `RoaringBitmap` as serialized by `stringdex.js`, with its compact forms for a
+/// handful of values.
+fn read_bitmap(data: &[u8]) -> io::Result<HashSet<u32>> {
+    let truncated = || invalid("truncated bitmap");
+    let u16_at = |i: usize| -> io::Result<u32> {
+        let b = data.get(i..i + 2).ok_or_else(truncated)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]) as u32)
+    };
+    let mut res = HashSet::new();
+    let Some(&tag) = data.first() else {
+        return Ok(res);
+    };
+    match tag {
+        0 => {}
+        // A run of `tag & 0xf` values.
+        0xe1..=0xef => {
+            let first = (u16_at(3)? << 16) | u16_at(1)?;
+            res.extend(first..first.saturating_add((tag & 0xf) as u32));
+        }
+        // The first value, then the distance to each next one in two bytes, or one.
+        0xd1..=0xdf | 0xf1..=0xff => {
+            let wide = tag > 0xf0;
+            let mut value = (u16_at(3)? << 16) | u16_at(1)?;
+            res.insert(value);
+            let mut i = 5;
+            for _ in 1..tag & 0xf {
+                let delta = if wide {
+                    u16_at(i)?
+                } else {
+                    *data.get(i).ok_or_else(truncated)? as u32
+                };
+                i += if wide { 2 } else { 1 };
+                value = value.wrapping_add(delta);
+                res.insert(value);
+            }
+        }
+        // Up to 57 values of four bytes each.
+        0x01..=0x39 => {
+            for j in 0..tag as usize {
+                res.insert((u16_at(3 + j * 4)? << 16) | u16_at(1 + j * 4)?);
+            }
+        }
+        // The standard roaring format, without and with run containers.
+        0x3a | 0x3b => {
+            let runs = tag == 0x3b;
+            let (size, mut i) = match runs {
+                true => (u16_at(2)? as usize + 1, 4),
+                false => {
+                    let b = data.get(4..8).ok_or_else(truncated)?;
+                    (u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize, 8)
+                }
+            };
+            let is_run = match runs {
+                true => {
+                    let len = size.div_ceil(8);
+                    i += len;
+                    data.get(i - len..i).ok_or_else(truncated)?
+                }
+                false => &[],
+            };
+            let headers = i;
+            i += size * 4;
+            if !runs || size >= 4 {
+                // Container offsets, which are implied by the sizes anyway.
+                i += size * 4;
+            }
+            for j in 0..size {
+                let key = u16_at(headers + j * 4)? << 16;
+                let cardinality = u16_at(headers + j * 4 + 2)? as usize + 1;
+                if is_run.get(j / 8).is_some_and(|b| b & (1 << (j % 8)) != 0) {
+                    let count = u16_at(i)? as usize;
+                    i += 2;
+                    for _ in 0..count {
+                        let start = u16_at(i)?;
+                        let len = u16_at(i + 2)?;
+                        res.extend((start..=start + len).map(|v| key | v));
+                        i += 4;
+                    }
+                } else if cardinality >= 4096 {
+                    let bits = data.get(i..i + 8192).ok_or_else(truncated)?;
+                    for v in 0..65536u32 {
+                        if bits[v as usize / 8] & (1 << (v % 8)) != 0 {
+                            res.insert(key | v);
+                        }
+                    }
+                    i += 8192;
+                } else {
+                    for k in 0..cardinality {
+                        res.insert(key | u16_at(i + k * 2)?);
+                    }
+                    i += cardinality * 2;
+                }
+            }
+        }
+        _ => return Err(invalid(format!("unknown bitmap tag {:#x}", tag))),
+    }
+    Ok(res)
+}
+
+/// Rows of a column chunk, without the empty ones. Each row is a length and bytes, or a
+/// single byte from `0` to `?` repeating one of the last 16 rows read.
+fn read_chunk(mut data: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+    let mut rows: Vec<Vec<u8>> = Vec::new();
+    let mut recent: Vec<usize> = Vec::new();
+    while let Some(&c) = data.first() {
+        if (48..64).contains(&c) {
+            let row = recent
+                .get(recent.len().wrapping_sub(1 + (c - 48) as usize))
+                .ok_or_else(|| invalid("bad back reference"))?;
+            rows.push(rows[*row].clone());
+            data = &data[1..];
+            continue;
+        }
+        let (len, rest) = read_varint(data)?;
+        let len = len as usize;
+        let row = rest.get(..len).ok_or_else(|| invalid("truncated row"))?;
+        recent.push(rows.len());
+        if recent.len() > 16 {
+            recent.remove(0);
+        }
+        rows.push(row.to_vec());
+        data = &rest[len..];
+    }
+    Ok(rows)
+}
+
+#[derive(Deserialize)]
+struct ColumnInfo {
+    /// Rows in each chunk, not counting empty ones.
+    #[serde(rename = "N")]
+    counts: String,
+    /// Hash of each chunk, naming its file.
+    #[serde(rename = "H")]
+    hashes: String,
+    /// Bitmap of empty rows.
+    #[serde(rename = "E")]
+    empty: String,
+}
+
+/// A `search.index` directory.
+struct Stringdex<'a> {
+    zup: &'a Reader,
+    flavor: &'a str,
+    columns: HashMap<String, ColumnInfo>,
+}
+
+impl<'a> Stringdex<'a> {
+    fn new(zup: &'a Reader, flavor: &'a str, root: &[u8]) -> io::Result<Self> {
+        let json = call_arg(root, "rr_")?;
+        let json = json
+            .strip_prefix(b"'")
+            .and_then(|j| j.strip_suffix(b"'"))
+            .ok_or_else(|| invalid("root.js isn't a string"))?;
+        let columns = serde_json::from_slice(json)?;
+        Ok(Self {
+            zup,
+            flavor,
+            columns,
+        })
+    }
+
+    /// All rows of a column, with empty ones.
+    fn column(&self, name: &str) -> io::Result<Vec<Vec<u8>>> {
+        let info = self
+            .columns
+            .get(name)
+            .ok_or_else(|| invalid(format!("no {} column", name)))?;
+        let hashes = BASE64.decode(&info.hashes).map_err(invalid_base64)?;
+        let empty = read_bitmap(&BASE64.decode(&info.empty).map_err(invalid_base64)?)?;
+
+        let mut rows = Vec::new();
+        let mut counts = info.counts.as_bytes();
+        let mut chunks = hashes.chunks(6);
+        while !counts.is_empty() {
+            let (count, rest) = read_varint(counts)?;
+            counts = rest;
+            let hash = chunks.next().ok_or_else(|| invalid("missing chunk hash"))?;
+            let file = format!("{}.js", hex(hash));
+            let data = self
+                .zup
+                .read(&["flavors", self.flavor, "search.index", name, &file])?;
+            let data = match call_arg(&data, "rd_") {
+                Ok(s) => latin1(&serde_json::from_slice::<String>(s)?)?,
+                Err(_) => {
+                    let s: String = serde_json::from_slice(call_arg(&data, "rb_")?)?;
+                    BASE64.decode(s).map_err(invalid_base64)?
+                }
+            };
+            let chunk = read_chunk(&data)?;
+            if chunk.len() as u64 != count {
+                return Err(invalid(format!("{} has the wrong number of rows", file)));
+            }
+            for row in chunk {
+                while empty.contains(&(rows.len() as u32)) {
+                    rows.push(Vec::new());
+                }
+                rows.push(row);
+            }
+        }
+        Ok(rows)
+    }
+
+    fn entries(&self, krate: &str) -> io::Result<Vec<Entry>> {
+        let names = self.column("name")?;
+        let entries = self.column("entry")?;
+        let paths = self.column("path")?;
+
+        let name = |id: usize| -> io::Result<&str> {
+            let row = names.get(id).ok_or_else(|| invalid("bad row reference"))?;
+            std::str::from_utf8(row).map_err(|_| invalid("name isn't UTF-8"))
+        };
+        // `[kind, module path, exact module path]` for modules and anything that can be a parent.
+        let path = |id: usize| -> io::Result<(u64, String)> {
+            let row = paths.get(id).ok_or_else(|| invalid("bad row reference"))?;
+            let (ty, module): (u64, String) = match serde_json::from_slice::<Vec<Value>>(row)?[..] {
+                [Value::Number(ref ty), Value::String(ref module), ..] => {
+                    (ty.as_u64().unwrap_or(u64::MAX), module.clone())
+                }
+                _ => return Err(invalid("bad path row")),
+            };
+            Ok((ty, module))
+        };
+
+        let mut res = Vec::new();
+        for (id, row) in entries.iter().enumerate() {
+            if row.is_empty() {
+                continue;
+            }
+            // `[crate, kind, module, exact module, parent, trait, deprecated, unstable,
+            // disambiguator]`, with rows other than the crate's counted from 1 so 0 is none.
+            let row: Vec<Value> = serde_json::from_slice(row)?;
+            let num = |i: usize| row.get(i).and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+            if name(num(0))? != krate {
+                continue;
+            }
+            // Trait methods implemented on every type, like `from` and `into`, would bury
+            // everything else. The trait's own methods are still indexed.
+            if num(5) != 0 {
+                continue;
+            }
+            let module = match num(2) {
+                0 => continue,
+                m => match path(m - 1)?.1 {
+                    p if p.is_empty() => name(m - 1)?.to_string(),
+                    p => format!("{}::{}", p, name(m - 1)?),
+                },
+            };
+            let parent = match num(4) {
+                0 => None,
+                p => match kind(path(p - 1)?.0) {
+                    Some(kind) => Some((kind, name(p - 1)?.to_string())),
+                    None => continue,
+                },
+            };
+            let Some(kind) = kind(num(1) as u64) else {
+                continue;
+            };
+            res.push(Entry {
+                name: name(id)?.to_string(),
+                kind,
+                module,
+                parent,
+                disambiguator: row.get(8).and_then(|d| d.as_str()).map(String::from),
+            });
+        }
+        Ok(res)
+    }
+}
+
+fn invalid_base64(e: base64::DecodeError) -> io::Error {
+    invalid(format!("bad base64: {}", e))
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Bytes of a JS string holding one byte per character.
+fn latin1(s: &str) -> io::Result<Vec<u8>> {
+    s.chars()
+        .map(|c| u8::try_from(c).map_err(|_| invalid("chunk isn't bytes")))
+        .collect()
+}
+
+/// Decoder for the hex "VLQ" lists in `search-index.js`: numbers as in [`read_varint`] with the
+/// sign in the lowest bit, `` ` `` for 0, and `0` to `?` repeating one of the last 16 numbers.
+fn read_vlq_list(mut data: &[u8]) -> io::Result<Vec<i64>> {
+    let mut res = Vec::new();
+    let mut recent: Vec<i64> = Vec::new();
+    while let Some(&c) = data.first() {
+        match c {
+            48..64 => {
+                let n = recent
+                    .get(recent.len().wrapping_sub(1 + (c - 48) as usize))
+                    .ok_or_else(|| invalid("bad back reference"))?;
+                res.push(*n);
+                data = &data[1..];
+            }
+            96 => {
+                res.push(0);
+                data = &data[1..];
+            }
+            _ => {
+                let (n, rest) = read_varint(data)?;
+                let n = match n & 1 {
+                    1 => -((n >> 1) as i64),
+                    _ => (n >> 1) as i64,
+                };
+                res.push(n);
+                recent.push(n);
+                if recent.len() > 16 {
+                    recent.remove(0);
+                }
+                data = rest;
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// The contents of the single-quoted JS string `s` starts with, which rustdoc wraps the JSON in.
+fn unescape_js(s: &[u8]) -> Option<Vec<u8>> {
+    let mut res = Vec::with_capacity(s.len());
+    let mut iter = s.iter();
+    while let Some(&c) = iter.next() {
+        match c {
+            b'\'' => return Some(res),
+            b'\\' => match iter.next()? {
+                b'\n' => {}
+                &c => res.push(c),
+            },
+            c => res.push(c),
+        }
+    }
+    None
+}
+
+/// Entries of `krate` in a `search-index.js`, like `var searchIndex = new Map(JSON.parse('[["krate",{...}]]'))`.
+fn legacy_entries(js: &[u8], krate: &str) -> io::Result<Vec<Entry>> {
+    let start = js
+        .windows(12)
+        .position(|w| w == b"JSON.parse('")
+        .ok_or_else(|| invalid("no JSON in search-index.js"))?
+        + 12;
+    let json = unescape_js(&js[start..]).ok_or_else(|| invalid("unterminated string"))?;
+    let index: Value = serde_json::from_slice(&json)?;
+    // An object by crate name, or pairs of crate name and object.
+    let krate_index = match &index {
+        Value::Object(crates) => crates.get(krate),
+        Value::Array(crates) => crates.iter().find_map(|c| match c.as_array()?.as_slice() {
+            [Value::String(name), index] if name == krate => Some(index),
+            _ => None,
+        }),
+        _ => None,
+    };
+    let Some(index) = krate_index else {
+        return Ok(Vec::new());
+    };
+
+    let numbers = |key: &str| -> io::Result<Vec<i64>> {
+        match &index[key] {
+            Value::String(s) => read_vlq_list(s.as_bytes()),
+            Value::Array(a) => Ok(a.iter().map(|n| n.as_i64().unwrap_or(0)).collect()),
+            _ => Ok(Vec::new()),
+        }
+    };
+    let types: Vec<u64> = match &index["t"] {
+        Value::String(s) => s.bytes().map(|c| c.wrapping_sub(b'A') as u64).collect(),
+        _ => numbers("t")?.into_iter().map(|n| n as u64).collect(),
+    };
+    let names: Vec<&str> = index["n"]
+        .as_array()
+        .map(|a| a.iter().map(|n| n.as_str().unwrap_or("")).collect())
+        .unwrap_or_default();
+    let pairs = |key: &str| -> BTreeMap<usize, &Value> {
+        index[key]
+            .as_array()
+            .map(|a| {
+                a.iter()
+                    .filter_map(|p| match p.as_array()?.as_slice() {
+                        [i, v] => Some((i.as_u64()? as usize, v)),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    // Module paths, each one holding until the next item that has one.
+    let modules = pairs("q");
+    let disambiguators = pairs("b");
+    // Parents as an index into `p` plus one, 0 for none.
+    let parent_ids = numbers("i")?;
+    let parents: Vec<(u64, &str)> = index["p"]
+        .as_array()
+        .map(|a| {
+            a.iter()
+                .map(|p| {
+                    (
+                        p[0].as_u64().unwrap_or(u64::MAX),
+                        p[1].as_str().unwrap_or(""),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut res = Vec::new();
+    let mut name = "";
+    let mut module = "";
+    for (i, ty) in types.into_iter().enumerate() {
+        // An empty name is the same as the previous item's.
+        match names.get(i) {
+            Some(n) if !n.is_empty() => name = n,
+            _ => {}
+        }
+        if let Some(m) = modules.get(&i).and_then(|m| m.as_str()) {
+            module = m;
+        }
+        let parent = match parent_ids.get(i) {
+            Some(&p) if p > 0 => {
+                let (ty, name) = parents
+                    .get(p as usize - 1)
+                    .ok_or_else(|| invalid("bad parent reference"))?;
+                match kind(*ty) {
+                    Some(kind) => Some((kind, name.to_string())),
+                    None => continue,
+                }
+            }
+            _ => None,
+        };
+        let Some(kind) = kind(ty) else {
+            continue;
+        };
+        res.push(Entry {
+            name: name.to_string(),
+            kind,
+            module: module.to_string(),
+            parent,
+            disambiguator: disambiguators
+                .get(&i)
+                .and_then(|d| d.as_str())
+                .map(String::from),
+        });
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varints() {
+        assert_eq!(read_varint(b"c").unwrap(), (3, &b""[..]));
+        assert_eq!(read_varint(b"Aca").unwrap(), (0x13, &b"a"[..]));
+        assert!(read_varint(b"A").is_err());
+    }
+
+    #[test]
+    fn bitmaps() {
+        let set = |v: &[u32]| v.iter().copied().collect::<HashSet<_>>();
+        assert_eq!(read_bitmap(&[]).unwrap(), set(&[]));
+        // Two values of four bytes each.
+        assert_eq!(
+            read_bitmap(&[2, 5, 0, 0, 0, 1, 0, 1, 0]).unwrap(),
+            set(&[5, 0x10001])
+        );
+        // A run of three from 7.
+        assert_eq!(read_bitmap(&[0xe3, 7, 0, 0, 0]).unwrap(), set(&[7, 8, 9]));
+        // 7, then the next one 2 further in one byte, or 300 further in two.
+        assert_eq!(read_bitmap(&[0xd2, 7, 0, 0, 0, 2]).unwrap(), set(&[7, 9]));
+        assert_eq!(
+            read_bitmap(&[0xf2, 7, 0, 0, 0, 0x2c, 0x01]).unwrap(),
+            set(&[7, 307])
+        );
+        // Standard format: one array container with 1 and 4.
+        let std = [
+            0x3a, 0x30, 0, 0, 1, 0, 0, 0, // cookie, one container
+            0, 0, 1, 0, // key 0, two values
+            16, 0, 0, 0, // offset
+            1, 0, 4, 0,
+        ];
+        assert_eq!(read_bitmap(&std).unwrap(), set(&[1, 4]));
+        // With runs: one run container holding 10 to 12.
+        let runs = [
+            0x3b, 0x30, 0, 0, // cookie, one container
+            1, // it's a run
+            0, 0, 2, 0, // key 0, three values
+            1, 0, 10, 0, 2, 0,
+        ];
+        assert_eq!(read_bitmap(&runs).unwrap(), set(&[10, 11, 12]));
+    }
+
+    #[test]
+    fn chunks() {
+        let rows = read_chunk(b"cabcb\x7fz0a1").unwrap();
+        assert_eq!(rows, [&b"abc"[..], b"\x7fz", b"\x7fz", b"1"]);
+        let rows = read_chunk(b"aaab1").unwrap();
+        assert_eq!(rows, [&b"a"[..], b"b", b"a"]);
+        assert!(read_chunk(b"dab").is_err());
+    }
+
+    #[test]
+    fn vlq_lists() {
+        assert_eq!(read_vlq_list(b"`cdA`0").unwrap(), [0, -1, 2, 8, 8]);
+    }
+
+    #[test]
+    fn legacy() {
+        let js = br#"var searchIndex = new Map(JSON.parse('[\
+["demo",{"t":"CFNGP","n":["gpio","Output","set_high","Level","High"],"q":[[0,"demo"],[1,"demo::gpio"]],"i":"``b`d","p":[[5,"Output"],[6,"Level"]],"b":[[2,"impl-Output"]]}],\
+["other",{"t":"F","n":["It\'s"],"q":[[0,"other"]],"i":"`","p":[]}]\
+]'));
+if (typeof exports !== 'undefined') exports.searchIndex = searchIndex;"#;
+        let entries = legacy_entries(js, "demo").unwrap();
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|e| (e.kind, e.module.as_str(), e.name.as_str(), e.parent.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("mod", "demo", "gpio", None),
+                ("struct", "demo::gpio", "Output", None),
+                (
+                    "method",
+                    "demo::gpio",
+                    "set_high",
+                    Some(("struct", "Output".to_string()))
+                ),
+                ("enum", "demo::gpio", "Level", None),
+                (
+                    "variant",
+                    "demo::gpio",
+                    "High",
+                    Some(("enum", "Level".to_string()))
+                ),
+            ]
+        );
+        assert_eq!(entries[2].disambiguator.as_deref(), Some("impl-Output"));
+
+        let entries = legacy_entries(js, "other").unwrap();
+        assert_eq!(entries[0].name, "It's");
+        assert!(legacy_entries(js, "missing").unwrap().is_empty());
+    }
+}
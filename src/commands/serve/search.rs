@@ -0,0 +1,239 @@
+//! Item search across crates, over the search index rustdoc writes in each flavor. It has
+//! everything rustdoc's own search box finds, including methods, fields and variants.
+
+use lru::LruCache;
+use serde::Serialize;
+use std::io;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+use super::rustdoc_index::{self, Entry};
+use crate::common::zup::read::Reader;
+
+/// Rustdoc page prefixes for item kinds, i.e. `struct` for `struct.Uart.html`.
+pub const KINDS: &[&str] = &[
+    "struct",
+    "enum",
+    "trait",
+    "union",
+    "type",
+    "fn",
+    "macro",
+    "attr",
+    "derive",
+    "constant",
+    "static",
+    "traitalias",
+    "primitive",
+    "keyword",
+];
+
+/// An item documented in one flavor of a crate.
+#[derive(Clone)]
+pub struct Item {
+    pub name: String,
+    pub kind: &'static str,
+    /// Module path within the crate, without the crate name.
+    pub module: Vec<String>,
+    /// Type, trait or `Enum::Variant` that methods, fields and variants are members of.
+    pub parent: Option<String>,
+    /// Path of the item's page within the flavor, with an anchor for members.
+    pub page: String,
+}
+
+#[derive(Serialize)]
+pub struct Hit {
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub version: String,
+    pub flavor: String,
+    pub name: String,
+    pub kind: &'static str,
+    pub path: String,
+    pub url: String,
+}
+
+/// Crate, version and flavor.
+type Key = (String, String, String);
+
+/// How many item lists to keep besides one per crate. Each is one crate version and flavor,
+/// and PACs have tens of thousands of items.
+const EXTRA_INDEXED: usize = 32;
+
+/// An item list, set once built. Concurrent requests for the same list wait for one build.
+type Slot = Arc<OnceCell<Arc<Vec<Item>>>>;
+
+/// Lazily built item lists, one per crate version and flavor, of the least recently used kept.
+///
+/// A search goes through one list of every crate, so there's room for that and then some.
+pub struct SearchIndex {
+    items: Mutex<LruCache<Key, Slot>>,
+}
+
+impl Default for SearchIndex {
+    fn default() -> Self {
+        Self {
+            items: Mutex::new(LruCache::new(NonZeroUsize::new(EXTRA_INDEXED).unwrap())),
+        }
+    }
+}
+
+/// Rustdoc's page and anchor for an entry, like `buildHrefAndPath` in its `search.js` does.
+/// `None` for entries that don't have their own docs in the crate, like re-exports.
+fn item(krate: &str, entry: Entry) -> Option<Item> {
+    let mut module: Vec<String> = entry.module.split("::").map(String::from).collect();
+    if module.first()? != krate || matches!(entry.kind, "externcrate" | "import") {
+        return None;
+    }
+    module.remove(0);
+
+    let mut page = String::new();
+    let parent = match entry.parent {
+        None => {
+            for m in &module {
+                page.push_str(m);
+                page.push('/');
+            }
+            match entry.kind {
+                "mod" => page.push_str(&format!("{}/index.html", entry.name)),
+                kind => page.push_str(&format!("{}.{}.html", kind, entry.name)),
+            }
+            None
+        }
+        Some((parent_kind, parent_name)) => {
+            // Fields of enum variants are in the enum's module path, and on the enum's page.
+            let (page_kind, page_name, anchor, parent) =
+                if entry.kind == "structfield" && parent_kind == "variant" {
+                    let enum_name = module.pop()?;
+                    let anchor = format!("variant.{}.field.{}", parent_name, entry.name);
+                    let parent = format!("{}::{}", enum_name, parent_name);
+                    ("enum", enum_name, anchor, parent)
+                } else {
+                    let anchor = format!("{}.{}", entry.kind, entry.name);
+                    (parent_kind, parent_name.clone(), anchor, parent_name)
+                };
+            for m in &module {
+                page.push_str(m);
+                page.push('/');
+            }
+            page.push_str(&format!("{}.{}.html#", page_kind, page_name));
+            if let Some(d) = &entry.disambiguator {
+                page.push_str(d);
+                page.push('/');
+            }
+            page.push_str(&anchor);
+            Some(parent)
+        }
+    };
+    Some(Item {
+        name: entry.name,
+        kind: entry.kind,
+        module,
+        parent,
+        page,
+    })
+}
+
+/// Items documented in `flavor` of the given archive, from rustdoc's search index.
+fn build(zup: &Reader, krate: &str, flavor: &str) -> io::Result<Vec<Item>> {
+    let krate = krate.replace('-', "_");
+    let entries = match rustdoc_index::read(zup, flavor, &krate) {
+        Ok(entries) => entries,
+        // Kept as an empty list, so it isn't looked for again on every search.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            log::warn!("no search index in {} {}: {}", krate, flavor, e);
+            Vec::new()
+        }
+        Err(e) => return Err(e),
+    };
+    Ok(entries
+        .into_iter()
+        .filter_map(|e| item(&krate, e))
+        .collect())
+}
+
+impl SearchIndex {
+    /// Items documented in `flavor` of the given archive, built on a blocking thread if needed.
+    pub async fn items(
+        &self,
+        zup: Arc<Reader>,
+        krate: &str,
+        version: &str,
+        flavor: &str,
+    ) -> io::Result<Arc<Vec<Item>>> {
+        let key = (krate.to_string(), version.to_string(), flavor.to_string());
+        let cell = self
+            .items
+            .lock()
+            .unwrap()
+            .get_or_insert(key, Default::default)
+            .clone();
+        let items = cell
+            .get_or_try_init(|| async {
+                let (krate, flavor) = (krate.to_string(), flavor.to_string());
+                let items = tokio::task::spawn_blocking(move || build(&zup, &krate, &flavor))
+                    .await
+                    .map_err(io::Error::other)??;
+                Ok::<_, io::Error>(Arc::new(items))
+            })
+            .await?;
+        Ok(items.clone())
+    }
+
+    /// Drop every list, and make room for one list per crate of a catalog with `crates`.
+    pub fn reset(&self, crates: usize) {
+        let mut items = self.items.lock().unwrap();
+        items.clear();
+        items.resize(NonZeroUsize::new(crates + EXTRA_INDEXED).unwrap());
+    }
+}
+
+/// How well `item` matches the lowercased query, lower is better. `None` if it doesn't.
+///
+/// With a query like `gpio::out`, the last segment is matched against the name and the rest
+/// has to appear in the item's path.
+pub fn score(item: &Item, krate: &str, query: &str) -> Option<u32> {
+    let name = item.name.to_lowercase();
+    let (haystack, needle) = match query.rsplit_once("::") {
+        Some((prefix, last)) => {
+            let path = rust_path(krate, item).to_lowercase();
+            if !path.contains(&format!("{}::", prefix)) {
+                return None;
+            }
+            (name, last.to_string())
+        }
+        None => (name, query.to_string()),
+    };
+
+    let quality = if haystack == needle {
+        0
+    } else if haystack.starts_with(&needle) {
+        1
+    } else if haystack.contains(&needle) {
+        2
+    } else {
+        return None;
+    };
+    // Prefer types and traits over functions and modules, those over members of types, and
+    // shallow items over deep ones.
+    let kind = match item.kind {
+        "struct" | "enum" | "trait" | "union" => 0,
+        "type" | "macro" | "fn" => 1,
+        _ if item.parent.is_some() => 3,
+        _ => 2,
+    };
+    Some(quality * 1000 + kind * 100 + item.module.len() as u32)
+}
+
+/// Full path of an item, e.g. `embassy_nrf::uarte::Uarte` or `embassy_nrf::uarte::Uarte::new`.
+pub fn rust_path(krate: &str, item: &Item) -> String {
+    let mut res = krate.replace('-', "_");
+    for m in item.module.iter().chain(&item.parent) {
+        res.push_str("::");
+        res.push_str(m);
+    }
+    res.push_str("::");
+    res.push_str(&item.name);
+    res
+}
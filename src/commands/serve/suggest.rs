@@ -0,0 +1,106 @@
+use std::io;
+
+use crate::common::zup::read::{Node, Reader};
+
+/// How many similarly named pages to suggest.
+const MAX_SIMILAR: usize = 5;
+
+/// How alike two item names have to be to count as similar, from 0 to 1.
+const CUTOFF: f32 = 0.7;
+
+/// Directories with more entries than this, like the register modules of PACs, only get
+/// suggestions for items whose kind changed. Fuzzy matching is quadratic in name length for
+/// every entry.
+const MAX_FUZZY_CANDIDATES: usize = 5000;
+
+/// The item a page or module directory is for, e.g. `Uart` for `struct.Uart.html`.
+fn item_name(name: &str) -> Option<&str> {
+    match name.strip_suffix(".html") {
+        Some(page) => page.split_once('.').map(|(_, item)| item),
+        None if !name.contains('.') => Some(name),
+        None => None,
+    }
+}
+
+/// Pages in `flavor` with a name like the missing `path`, relative to the flavor.
+///
+/// Looks in the deepest directory of `path` that exists, for pages and modules whose item name,
+/// without the kind and `.html`, is like the missing one. Items whose kind changed, like a struct
+/// that became an enum, come first, then names that look like typos or renames.
+pub fn similar_pages(zup: &Reader, flavor: &str, path: &[&str]) -> io::Result<Vec<String>> {
+    let mut dir_path = vec!["flavors", flavor];
+    let mut dir = match zup.open(&dir_path)? {
+        Node::Directory(d) => d,
+        Node::File(_) => return Ok(Vec::new()),
+    };
+    let mut i = 0;
+    while i < path.len() {
+        match dir.child(path[i])? {
+            Some(Node::Directory(d)) => dir = d,
+            _ => break,
+        }
+        dir_path.push(path[i]);
+        i += 1;
+    }
+    let Some(missing) = path.get(i) else {
+        return Ok(Vec::new());
+    };
+    let rest = &path[i + 1..];
+    let prefix = path[..i].iter().fold(String::new(), |mut s, p| {
+        s.push_str(p);
+        s.push('/');
+        s
+    });
+
+    let Some(missing_item) = item_name(missing) else {
+        return Ok(Vec::new());
+    };
+
+    let children = dir.children()?;
+    let pages: Vec<(&str, &str)> = children
+        .iter()
+        .filter_map(|(n, _)| Some((item_name(n)?, n.as_str())))
+        .collect();
+    let mut items: Vec<&str> = pages.iter().map(|(item, _)| *item).collect();
+    items.sort_unstable();
+    items.dedup();
+
+    // The same item first, for a struct that became an enum, then typos and renames.
+    let close = match items.len() <= MAX_FUZZY_CANDIDATES {
+        true => similar::get_close_matches(missing_item, &items, MAX_SIMILAR, CUTOFF),
+        false => items.into_iter().filter(|i| *i == missing_item).collect(),
+    };
+    let mut matches: Vec<&str> = Vec::new();
+    for item in close {
+        matches.extend(
+            pages
+                .iter()
+                .filter(|(i, n)| *i == item && n != missing)
+                .map(|(_, n)| *n),
+        );
+    }
+    matches.truncate(MAX_SIMILAR);
+
+    let mut res = Vec::new();
+    for name in matches {
+        let Some((_, node)) = children.iter().find(|(n, _)| n == name) else {
+            continue;
+        };
+        match node {
+            Node::File(_) if rest.is_empty() => res.push(format!("{}{}", prefix, name)),
+            Node::File(_) => {}
+            // A renamed module: keep the rest of the path if it's still there.
+            Node::Directory(_) => {
+                let mut full = dir_path.clone();
+                full.push(name);
+                full.extend_from_slice(rest);
+                let page = match zup.open(&full) {
+                    Ok(Node::File(_)) => rest.join("/"),
+                    _ => "index.html".to_string(),
+                };
+                res.push(format!("{}{}/{}", prefix, name, page));
+            }
+        }
+    }
+    Ok(res)
+}
@@ -0,0 +1,134 @@
+use anyhow::Context as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::SystemTime;
+use tera::{Context, Tera};
+
+/// Templates compiled into the binary, used when no `--templates` directory is given.
+const EMBEDDED: &[(&str, &str)] = &[
+    ("head.html", include_str!("../../../templates/head.html")),
+    ("nav.html", include_str!("../../../templates/nav.html")),
+    ("404.html", include_str!("../../../templates/404.html")),
+    ("500.html", include_str!("../../../templates/500.html")),
+];
+
+pub struct Templates {
+    /// Directory to watch for changes, in reload mode.
+    reload_dir: Option<PathBuf>,
+    inner: RwLock<Loaded>,
+}
+
+struct Loaded {
+    tera: Tera,
+    mtimes: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+/// Load the templates in `dir`, with the embedded ones filling in for any it doesn't have.
+fn load_dir(dir: &Path) -> anyhow::Result<Tera> {
+    // Tera finds no templates in a missing directory, which would go unnoticed with the fallbacks.
+    anyhow::ensure!(
+        dir.is_dir(),
+        "Templates directory {} doesn't exist",
+        dir.display()
+    );
+    let mut tera = Tera::new(&format!("{}/**/*.html", dir.display()))
+        .with_context(|| format!("Failed to load templates from {}", dir.display()))?;
+    let missing: Vec<_> = EMBEDDED
+        .iter()
+        .filter(|(name, _)| !tera.get_template_names().any(|n| n == *name))
+        .copied()
+        .collect();
+    tera.add_raw_templates(missing)?;
+    Ok(tera)
+}
+
+/// Modification times of all templates under `dir`, the same `**/*.html` Tera loads, to notice
+/// edits.
+fn mtimes(dir: &Path) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let mut res = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for f in entries.flatten() {
+            let path = f.path();
+            if f.file_type().is_ok_and(|t| t.is_dir()) {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e == "html") {
+                let mtime = f.metadata().and_then(|m| m.modified()).ok();
+                res.push((path, mtime));
+            }
+        }
+    }
+    res.sort();
+    res
+}
+
+impl Templates {
+    pub fn embedded() -> anyhow::Result<Self> {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(EMBEDDED.iter().copied())?;
+        Ok(Self {
+            reload_dir: None,
+            inner: RwLock::new(Loaded {
+                tera,
+                mtimes: Vec::new(),
+            }),
+        })
+    }
+
+    /// Load templates from `dir`. With `reload`, they're re-read whenever a file in it changes.
+    pub fn load(dir: &Path, reload: bool) -> anyhow::Result<Self> {
+        let mtimes = mtimes(dir);
+        let tera = load_dir(dir)?;
+
+        Ok(Self {
+            reload_dir: reload.then(|| dir.to_path_buf()),
+            inner: RwLock::new(Loaded { tera, mtimes }),
+        })
+    }
+
+    fn reload_if_changed(&self, dir: &Path) {
+        let mtimes = mtimes(dir);
+        if self.inner.read().unwrap().mtimes == mtimes {
+            return;
+        }
+
+        // Keep serving the old templates if the new ones are broken, but don't retry until the next edit.
+        let tera = load_dir(dir);
+        let mut inner = self.inner.write().unwrap();
+        match tera {
+            Ok(tera) => {
+                inner.tera = tera;
+                log::info!("reloaded templates from {}", dir.display());
+            }
+            Err(e) => log::error!("reloading templates failed: {:?}", e),
+        }
+        inner.mtimes = mtimes;
+    }
+
+    /// Check that all templates the server needs are loaded.
+    pub fn check(&self) -> anyhow::Result<()> {
+        if let Some(dir) = &self.reload_dir {
+            self.reload_if_changed(dir);
+        }
+        let inner = self.inner.read().unwrap();
+        for (name, _) in EMBEDDED {
+            anyhow::ensure!(
+                inner.tera.get_template_names().any(|n| n == *name),
+                "template {} is missing",
+                name
+            );
+        }
+        Ok(())
+    }
+
+    pub fn render(&self, name: &str, context: &Context) -> tera::Result<String> {
+        if let Some(dir) = &self.reload_dir {
+            self.reload_if_changed(dir);
+        }
+        self.inner.read().unwrap().tera.render(name, context)
+    }
+}
@@ -0,0 +1,135 @@
+use clap::Parser;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::io;
+use std::path::PathBuf;
+
+use crate::common::zup::{
+    layout,
+    read::{File, Node, Reader},
+};
+
+struct Verifier<'r> {
+    reader: &'r Reader,
+    dirs: u64,
+    files: u64,
+    bytes: u64,
+    visited: HashSet<layout::Node>,
+    ancestors: Vec<layout::Node>,
+    problems: Vec<(String, String)>,
+}
+
+impl<'r> Verifier<'r> {
+    pub fn new(reader: &'r Reader) -> Self {
+        Self {
+            reader,
+            dirs: 0,
+            files: 0,
+            bytes: 0,
+            visited: HashSet::new(),
+            ancestors: Vec::new(),
+            problems: Vec::new(),
+        }
+    }
+
+    fn problem(&mut self, path: &str, e: impl Display) {
+        let path = if path.is_empty() { "/" } else { path };
+        self.problems.push((path.to_string(), e.to_string()));
+    }
+
+    pub fn walk(&mut self, n: Node<'_>, path: &str) {
+        let node = n.node();
+        if self.ancestors.contains(&node) {
+            self.problem(path, "directory cycle");
+            return;
+        }
+        // Dedup makes nodes reachable from several paths, they only need checking once.
+        if !self.visited.insert(node) {
+            return;
+        }
+        if let Err(e) = self.reader.check_range(node.range) {
+            self.problem(path, e);
+            return;
+        }
+
+        match n {
+            Node::Directory(n) => {
+                self.dirs += 1;
+                let children = match n.children() {
+                    Ok(c) => c,
+                    Err(e) => return self.problem(path, e),
+                };
+                self.ancestors.push(node);
+                for (name, c) in children {
+                    self.walk(c, &format!("{}/{}", path, name));
+                }
+                self.ancestors.pop();
+            }
+            Node::File(n) => {
+                self.files += 1;
+                match self.check_file(&n) {
+                    Ok(len) => self.bytes += len,
+                    Err(e) => self.problem(path, e),
+                }
+            }
+        }
+    }
+
+    /// Decompress a file and check it against its checksum, returning its size.
+    ///
+    /// Streamed, so files too large to read into memory in one go are checked too.
+    fn check_file(&self, f: &File<'_>) -> io::Result<u64> {
+        let mut hasher = blake3::Hasher::new();
+        let len = io::copy(&mut f.reader()?, &mut hasher)?;
+        if !self.reader.has_checksums() {
+            return Ok(len);
+        }
+        match self.reader.checksum(f.node())? {
+            Some(expected) if *hasher.finalize().as_bytes() == expected => Ok(len),
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "checksum mismatch",
+            )),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "node has no checksum",
+            )),
+        }
+    }
+}
+
+#[derive(Parser)]
+pub struct VerifyArgs {
+    /// Path to the .zup archive to verify
+    pub archive: PathBuf,
+}
+
+pub async fn run(args: VerifyArgs) -> anyhow::Result<()> {
+    let mut zup = Reader::new(&args.archive)?;
+    zup.set_verify(true);
+
+    let mut v = Verifier::new(&zup);
+    v.walk(zup.root_node(), "");
+
+    println!("archive {}", args.archive.display());
+    println!("version {}", zup.version());
+    println!("size {}", zup.file_size());
+    println!("checksums {}", zup.has_checksums());
+    println!("dirs {}", v.dirs);
+    println!("files {}", v.files);
+    println!("bytes {}", v.bytes);
+
+    if v.problems.is_empty() {
+        println!("ok");
+        return Ok(());
+    }
+
+    for (path, e) in &v.problems {
+        println!("FAIL {}: {}", path, e);
+    }
+    Err(anyhow::anyhow!(
+        "{} problem(s) found in {}",
+        v.problems.len(),
+        args.archive.display()
+    ))
+}
@@ -1,37 +1,60 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::fs;
 use std::path::PathBuf;
 
-use crate::common::zup::write::pack;
+use crate::commands::{diff, verify};
 use crate::common::CompressionArgs;
+use crate::common::zup::write::pack;
 
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct ZupArgs {
+    #[command(subcommand)]
+    pub command: Option<ZupCommand>,
+
     /// Input directory to compress
-    #[clap(short, long)]
-    pub input: PathBuf,
+    #[clap(short, long, required = true)]
+    pub input: Option<PathBuf>,
     /// Output .zup file
-    #[clap(short, long)]
-    pub output: PathBuf,
+    #[clap(short, long, required = true)]
+    pub output: Option<PathBuf>,
 
     #[clap(flatten)]
     pub compression: CompressionArgs,
 }
 
+#[derive(Subcommand)]
+pub enum ZupCommand {
+    /// Check the integrity of a zup archive
+    Verify(verify::VerifyArgs),
+    /// Compare two zup archives
+    Diff(diff::DiffArgs),
+}
+
 pub async fn run(args: ZupArgs) -> anyhow::Result<()> {
-    println!("Compressing directory: {:?}", args.input);
+    match args.command {
+        Some(ZupCommand::Verify(args)) => return verify::run(args).await,
+        Some(ZupCommand::Diff(args)) => return diff::run(args).await,
+        None => {}
+    }
+
+    // clap enforces these when no subcommand is given.
+    let input = args.input.unwrap();
+    let output = args.output.unwrap();
+
+    println!("Compressing directory: {:?}", input);
 
     // Create output directory if it doesn't exist
-    if let Some(p) = args.output.parent() {
+    if let Some(p) = output.parent() {
         fs::create_dir_all(p)?;
     }
 
     let compress = args.compression.to_config();
 
     // Pack the input directory using the new pack function
-    pack(&args.input, &args.output, compress)?;
+    pack(&input, &output, compress)?;
 
-    println!("Created archive: {:?}", args.output);
+    println!("Created archive: {:?}", output);
 
     Ok(())
 }
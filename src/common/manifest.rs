@@ -4,6 +4,9 @@ use std::collections::HashMap;
 #[derive(Serialize, Deserialize)]
 pub struct DocserverInfo {
     pub git_commit: String,
+    /// When the docs were built, in seconds since the Unix epoch. Missing in older archives.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_time: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -93,7 +96,7 @@ pub struct Metadata {
     pub embassy_docs: Docs,
 }
 
-#[derive(Deserialize, Default)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct Docs {
     #[serde(default)]
     pub flavors: Vec<DocsFlavor>,
@@ -107,7 +110,7 @@ pub struct Docs {
     pub src_base_git: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct DocsFlavor {
     // One of either has to be specified
     pub regex_feature: Option<String>,
@@ -21,15 +21,21 @@ pub struct CompressionArgs {
     /// Compress dictionary training set max size (only for .zup archives)
     #[clap(long, default_value = "100000000")]
     pub dict_train_size: usize,
+
+    /// Compress non-HTML files without the dictionary, so the server can send
+    /// them as-is to clients accepting `Content-Encoding: zstd` (only for .zup archives)
+    #[clap(long)]
+    pub dictless_assets: bool,
 }
 
 impl CompressionArgs {
     /// Convert to CompressConfig if compression is enabled
     pub fn to_config(&self) -> Option<crate::common::zup::write::CompressConfig> {
-        (!self.no_compress).then(|| crate::common::zup::write::CompressConfig {
+        (!self.no_compress).then_some(crate::common::zup::write::CompressConfig {
             level: self.compress_level,
             dict_size: self.dict_size,
             dict_train_size: self.dict_train_size,
+            dictless_assets: self.dictless_assets,
         })
     }
 }
@@ -0,0 +1,172 @@
+use bytes::Bytes;
+use lru::LruCache;
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use super::layout;
+use super::read::{Counters, Reader};
+
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct ReaderCacheStats {
+    pub readers: CacheStats,
+    pub nodes: CacheStats,
+}
+
+/// Size-bounded LRU cache of decompressed nodes, shared between readers.
+///
+/// `layout::Node`s are only unique within one archive, so entries are keyed
+/// by the id of the reader that inserted them as well.
+pub struct NodeCache {
+    max_bytes: usize,
+    inner: Mutex<NodeCacheInner>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+struct NodeCacheInner {
+    lru: LruCache<(u64, layout::Node), Bytes>,
+    bytes: usize,
+}
+
+impl NodeCache {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            inner: Mutex::new(NodeCacheInner {
+                lru: LruCache::unbounded(),
+                bytes: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, reader: u64, node: layout::Node) -> Option<Bytes> {
+        let res = self.inner.lock().unwrap().lru.get(&(reader, node)).cloned();
+        match res {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        res
+    }
+
+    pub fn insert(&self, reader: u64, node: layout::Node, data: Bytes) {
+        // Don't let a single huge node flush everything else out.
+        if data.len() > self.max_bytes / 8 {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.bytes += data.len();
+        if let Some(old) = inner.lru.put((reader, node), data) {
+            inner.bytes -= old.len();
+        }
+        while inner.bytes > self.max_bytes {
+            let Some((_, evicted)) = inner.lru.pop_lru() else {
+                break;
+            };
+            inner.bytes -= evicted.len();
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: inner.lru.len(),
+            bytes: inner.bytes,
+        }
+    }
+}
+
+/// Cache of open readers, keyed by archive path.
+///
+/// Entries are revalidated against the file's size and mtime on every lookup,
+/// so archives replaced on disk get reopened.
+pub struct ReaderCache {
+    nodes: Arc<NodeCache>,
+    inner: Mutex<LruCache<PathBuf, ReaderEntry>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+struct ReaderEntry {
+    len: u64,
+    mtime: Option<SystemTime>,
+    reader: Arc<Reader>,
+}
+
+impl ReaderCache {
+    pub fn new(max_readers: usize, nodes: Arc<NodeCache>) -> Self {
+        let max_readers = NonZeroUsize::new(max_readers).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            nodes,
+            inner: Mutex::new(LruCache::new(max_readers)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Get a reader for `path`, opening it if needed. Newly opened readers report to `counters`.
+    pub fn get(&self, path: &Path, counters: Arc<Counters>) -> io::Result<Arc<Reader>> {
+        let m = fs::metadata(path)?;
+        let mtime = m.modified().ok();
+
+        if let Some(e) = self.inner.lock().unwrap().get(path)
+            && e.len == m.len()
+            && e.mtime == mtime
+        {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(e.reader.clone());
+        }
+
+        // Open outside the lock, so a slow open doesn't stall other requests.
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        counters.opens.fetch_add(1, Ordering::Relaxed);
+        let mut reader = match Reader::new_cached(path, self.nodes.clone()) {
+            Ok(reader) => reader,
+            Err(e) => {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+                return Err(e.into());
+            }
+        };
+        reader.set_counters(counters);
+        let reader = Arc::new(reader);
+        self.inner.lock().unwrap().put(
+            path.to_path_buf(),
+            ReaderEntry {
+                len: m.len(),
+                mtime,
+                reader: reader.clone(),
+            },
+        );
+        Ok(reader)
+    }
+
+    pub fn stats(&self) -> ReaderCacheStats {
+        ReaderCacheStats {
+            readers: CacheStats {
+                hits: self.hits.load(Ordering::Relaxed),
+                misses: self.misses.load(Ordering::Relaxed),
+                entries: self.inner.lock().unwrap().len(),
+                bytes: 0,
+            },
+            nodes: self.nodes.stats(),
+        }
+    }
+}
@@ -1,5 +1,9 @@
 pub const FLAG_COMPRESSED: u32 = 1;
 pub const FLAG_DIR: u32 = 2;
+/// Compressed without the archive's dictionary, so the node is a standalone zstd frame.
+///
+/// Only written from version 4 on, since older readers would decompress these with the dictionary.
+pub const FLAG_NO_DICT: u32 = 4;
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
 pub struct Range {
@@ -45,8 +49,78 @@ impl Node {
     }
 }
 
+/// Directory entry in the name index of version 2+ directory nodes.
+///
+/// A directory node is a `u32` entry count, followed by that many entries
+/// sorted by name, followed by the concatenated names. `name_offset` is
+/// relative to the start of the names.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct DirEntry {
+    pub name_offset: u32,
+    pub name_len: u16,
+    pub node: Node,
+}
+
+impl DirEntry {
+    pub const LEN: usize = 26;
+    pub fn from_bytes(b: [u8; Self::LEN]) -> Self {
+        let name_offset = u32::from_le_bytes(b[0..4].try_into().unwrap());
+        let name_len = u16::from_le_bytes(b[4..6].try_into().unwrap());
+        let node = Node::from_bytes(b[6..26].try_into().unwrap());
+        Self {
+            name_offset,
+            name_len,
+            node,
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; Self::LEN] {
+        let mut res = [0; Self::LEN];
+        res[0..4].copy_from_slice(&self.name_offset.to_le_bytes());
+        res[4..6].copy_from_slice(&self.name_len.to_le_bytes());
+        res[6..26].copy_from_slice(&self.node.to_bytes());
+        res
+    }
+}
+
+/// Entry in the checksum table of version 3+ archives.
+///
+/// The table is sorted by `range` (offset, then length) and has one entry per
+/// unique node. `hash` is the blake3 hash of the node's uncompressed content.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Checksum {
+    pub range: Range,
+    pub hash: [u8; 32],
+}
+
+impl Checksum {
+    pub const LEN: usize = 48;
+    pub fn from_bytes(b: [u8; Self::LEN]) -> Self {
+        let range = Range::from_bytes(b[0..16].try_into().unwrap());
+        let hash = b[16..48].try_into().unwrap();
+        Self { range, hash }
+    }
+
+    pub fn to_bytes(self) -> [u8; Self::LEN] {
+        let mut res = [0; Self::LEN];
+        res[0..16].copy_from_slice(&self.range.to_bytes());
+        res[16..48].copy_from_slice(&self.hash);
+        res
+    }
+}
+
 pub const MAGIC: u32 = 0x2170755a;
-pub const VERSION: u32 = 1;
+/// Version written by the writer.
+///
+/// - 1: directory nodes are a plain list of (len8 name, node) entries.
+/// - 2: directory nodes are a sorted, fixed-stride `DirEntry` index.
+/// - 3: adds a `Checksum` table, referenced from a `SuperblockExt`.
+/// - 4: compressed nodes may be standalone zstd frames, marked with `FLAG_NO_DICT`.
+pub const VERSION: u32 = 4;
+/// First version with indexed directory nodes.
+pub const VERSION_INDEXED_DIRS: u32 = 2;
+/// First version with a `SuperblockExt` and a checksum table.
+pub const VERSION_CHECKSUMS: u32 = 3;
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub struct Superblock {
@@ -80,3 +154,26 @@ impl Superblock {
         res
     }
 }
+
+/// Superblock fields added in version 3, stored immediately before the `Superblock`.
+///
+/// It is kept separate so the `Superblock` at the end of the file has the
+/// same layout in every version and can be read before the version is known.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct SuperblockExt {
+    pub checksums: Range,
+}
+
+impl SuperblockExt {
+    pub const LEN: usize = 16;
+    pub fn from_bytes(b: [u8; Self::LEN]) -> Self {
+        let checksums = Range::from_bytes(b[0..16].try_into().unwrap());
+        Self { checksums }
+    }
+
+    pub fn to_bytes(self) -> [u8; Self::LEN] {
+        let mut res = [0; Self::LEN];
+        res[0..16].copy_from_slice(&self.checksums.to_bytes());
+        res
+    }
+}
@@ -1,3 +1,4 @@
+pub mod cache;
 pub mod layout;
 pub mod read;
 pub mod write;
@@ -1,39 +1,170 @@
+use bytes::Bytes;
 use std::cell::Cell;
+use std::cmp::Ordering;
+use std::fmt;
 use std::fs;
 use std::io;
-use std::io::Read;
+use std::io::{BufReader, Read};
 #[cfg(target_os = "windows")]
 use std::io::{Seek, SeekFrom};
 #[cfg(target_os = "linux")]
 use std::os::unix::fs::FileExt;
 use std::path::Path;
 use std::str;
+use std::sync::Arc;
+use std::sync::atomic::{self, AtomicU64};
+use std::time::SystemTime;
 use zstd::Decoder;
 use zstd::dict::DecoderDictionary;
 
+use super::cache::NodeCache;
 use super::layout;
 
+static NEXT_READER_ID: AtomicU64 = AtomicU64::new(0);
+
 #[cfg(target_os = "linux")]
-fn read_exact_at(file: &fs::File, buffer: &mut Vec<u8>, offset: u64) -> io::Result<()> {
+fn read_exact_at(file: &fs::File, buffer: &mut [u8], offset: u64) -> io::Result<()> {
     file.read_exact_at(buffer, offset)
 }
 
 #[cfg(target_os = "windows")]
-fn read_exact_at(mut file: &fs::File, buffer: &mut Vec<u8>, offset: u64) -> io::Result<()> {
+fn read_exact_at(mut file: &fs::File, buffer: &mut [u8], offset: u64) -> io::Result<()> {
     file.seek(SeekFrom::Start(offset))?;
     file.read_exact(buffer)
 }
 
+#[cfg(target_os = "linux")]
+fn read_at(file: &fs::File, buffer: &mut [u8], offset: u64) -> io::Result<usize> {
+    file.read_at(buffer, offset)
+}
+
+#[cfg(target_os = "windows")]
+fn read_at(mut file: &fs::File, buffer: &mut [u8], offset: u64) -> io::Result<usize> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.read(buffer)
+}
+
+/// Errors detected while opening an archive.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The file is too small to even hold a superblock.
+    Truncated {
+        file_size: u64,
+    },
+    BadMagic(u32),
+    UnsupportedVersion(u32),
+    /// A range points outside the data area of the file.
+    RangeOutOfBounds {
+        range: layout::Range,
+        file_size: u64,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Truncated { file_size } => write!(
+                f,
+                "truncated zup: file is {} bytes, superblock alone is {}",
+                file_size,
+                layout::Superblock::LEN
+            ),
+            Self::BadMagic(magic) => write!(
+                f,
+                "not a zup: bad magic {:#010x}, expected {:#010x}",
+                magic,
+                layout::MAGIC
+            ),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported zup version {}, this build reads up to {}",
+                version,
+                layout::VERSION
+            ),
+            Self::RangeOutOfBounds { range, file_size } => write!(
+                f,
+                "range out of bounds: offset {} len {} in a {} byte file",
+                range.offset, range.len, file_size
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// Check that `range` lies within the data area, i.e. before the superblock.
+fn check_range(range: layout::Range, data_end: u64, file_size: u64) -> Result<(), Error> {
+    match range.offset.checked_add(range.len) {
+        Some(end) if end <= data_end => Ok(()),
+        _ => Err(Error::RangeOutOfBounds { range, file_size }),
+    }
+}
+
+/// Counters a reader updates as it's used, for monitoring.
+///
+/// Can be shared between readers, e.g. to count per crate instead of per archive.
+#[derive(Default)]
+pub struct Counters {
+    pub opens: AtomicU64,
+    pub decompressions: AtomicU64,
+    pub errors: AtomicU64,
+}
+
 pub struct Reader {
     file: fs::File,
+    file_size: u64,
+    modified: Option<SystemTime>,
+    data_end: u64,
     superblock: layout::Superblock,
+    checksums: Option<layout::Range>,
     dict: Option<DecoderDictionary<'static>>,
+    id: u64,
+    cache: Option<Arc<NodeCache>>,
+    counters: Option<Arc<Counters>>,
+    verify: bool,
 }
 
 impl Reader {
-    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::open_file(path.as_ref(), None)
+    }
+
+    /// Open a reader that keeps decompressed nodes in a (possibly shared) cache.
+    pub fn new_cached<P: AsRef<Path>>(path: P, cache: Arc<NodeCache>) -> Result<Self, Error> {
+        Self::open_file(path.as_ref(), Some(cache))
+    }
+
+    fn open_file(path: &Path, cache: Option<Arc<NodeCache>>) -> Result<Self, Error> {
         let file = fs::File::open(path)?;
-        let file_size = file.metadata()?.len();
+        let m = file.metadata()?;
+        let file_size = m.len();
+        if file_size < layout::Superblock::LEN as u64 {
+            return Err(Error::Truncated { file_size });
+        }
 
         // Read the superblock from the end of the file
         let mut superblock_buf = vec![0u8; layout::Superblock::LEN];
@@ -44,8 +175,32 @@ impl Reader {
         )?;
 
         let superblock = layout::Superblock::from_bytes(superblock_buf.try_into().unwrap());
+        if superblock.magic != layout::MAGIC {
+            return Err(Error::BadMagic(superblock.magic));
+        }
+        if superblock.version == 0 || superblock.version > layout::VERSION {
+            return Err(Error::UnsupportedVersion(superblock.version));
+        }
+
+        let mut data_end = file_size - layout::Superblock::LEN as u64;
+        let mut checksums = None;
+        if superblock.version >= layout::VERSION_CHECKSUMS {
+            let Some(ext_offset) = data_end.checked_sub(layout::SuperblockExt::LEN as u64) else {
+                return Err(Error::Truncated { file_size });
+            };
+            let mut ext_buf = [0u8; layout::SuperblockExt::LEN];
+            read_exact_at(&file, &mut ext_buf, ext_offset)?;
+            let ext = layout::SuperblockExt::from_bytes(ext_buf);
+
+            data_end = ext_offset;
+            check_range(ext.checksums, data_end, file_size)?;
+            checksums = Some(ext.checksums);
+        }
+
+        check_range(superblock.root.range, data_end, file_size)?;
 
         let dict = if let Some(dict_range) = superblock.dict {
+            check_range(dict_range, data_end, file_size)?;
             let dict_data = Self::read_range(&file, dict_range)?;
             Some(DecoderDictionary::copy(&dict_data))
         } else {
@@ -54,37 +209,181 @@ impl Reader {
 
         Ok(Self {
             file,
+            file_size,
+            modified: m.modified().ok(),
+            data_end,
             superblock,
+            checksums,
             dict,
+            id: NEXT_READER_ID.fetch_add(1, atomic::Ordering::Relaxed),
+            cache,
+            counters: None,
+            verify: false,
         })
     }
 
+    /// Size of the archive file in bytes.
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    /// Modification time of the archive file, when the platform provides it.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    pub fn version(&self) -> u32 {
+        self.superblock.version
+    }
+
+    /// Check that `range` lies within the data area of this archive.
+    pub fn check_range(&self, range: layout::Range) -> Result<(), Error> {
+        check_range(range, self.data_end, self.file_size)
+    }
+
+    /// Count decompressions and read errors in `counters`.
+    pub fn set_counters(&mut self, counters: Arc<Counters>) {
+        self.counters = Some(counters);
+    }
+
+    fn count(&self, f: impl FnOnce(&Counters) -> &AtomicU64) {
+        if let Some(c) = &self.counters {
+            f(c).fetch_add(1, atomic::Ordering::Relaxed);
+        }
+    }
+
+    /// Whether the archive stores per-node checksums.
+    pub fn has_checksums(&self) -> bool {
+        self.checksums.is_some()
+    }
+
+    /// Verify every node read from disk against its stored checksum.
+    ///
+    /// Has no effect on archives without checksums.
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    /// Look up the stored blake3 hash of a node's uncompressed content.
+    ///
+    /// Returns `None` if the archive has no checksums or the node has no entry.
+    pub fn checksum(&self, node: layout::Node) -> io::Result<Option<[u8; 32]>> {
+        let Some(table) = self.checksums else {
+            return Ok(None);
+        };
+
+        // Binary search directly on disk, the table can be large and is rarely needed in full.
+        let key = (node.range.offset, node.range.len);
+        let (mut lo, mut hi) = (0, table.len / layout::Checksum::LEN as u64);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let mut buf = [0u8; layout::Checksum::LEN];
+            read_exact_at(
+                &self.file,
+                &mut buf,
+                table.offset + mid * layout::Checksum::LEN as u64,
+            )?;
+            let c = layout::Checksum::from_bytes(buf);
+            match (c.range.offset, c.range.len).cmp(&key) {
+                Ordering::Equal => return Ok(Some(c.hash)),
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+            }
+        }
+        Ok(None)
+    }
+
     fn read_range(file: &fs::File, r: layout::Range) -> io::Result<Vec<u8>> {
         if r.len > 100_000_000 {
             return Err(io::Error::other("range too large"));
         }
 
         let mut buffer = vec![0u8; r.len as usize];
-        read_exact_at(&file, &mut buffer, r.offset)?;
+        read_exact_at(file, &mut buffer, r.offset)?;
         Ok(buffer)
     }
 
-    fn read_node(&self, node: layout::Node) -> io::Result<Vec<u8>> {
+    fn read_node(&self, node: layout::Node) -> io::Result<Bytes> {
+        if let Some(cache) = &self.cache
+            && let Some(data) = cache.get(self.id, node)
+        {
+            return Ok(data);
+        }
+
+        let data = match self.read_node_uncached(node) {
+            Ok(data) => Bytes::from(data),
+            Err(e) => {
+                self.count(|c| &c.errors);
+                return Err(e);
+            }
+        };
+        if let Some(cache) = &self.cache {
+            cache.insert(self.id, node, data.clone());
+        }
+        Ok(data)
+    }
+
+    /// Uncompressed size of a node.
+    ///
+    /// For compressed nodes this comes from the zstd frame header, which the
+    /// writer always fills in, so nothing has to be decompressed.
+    fn node_size(&self, node: layout::Node) -> io::Result<u64> {
+        if node.flags & layout::FLAG_COMPRESSED == 0 {
+            return Ok(node.range.len);
+        }
+
+        // Maximum size of a zstd frame header.
+        const FRAME_HEADER_MAX: u64 = 18;
+        self.check_range(node.range)?;
+        let header = Self::read_range(
+            &self.file,
+            layout::Range {
+                offset: node.range.offset,
+                len: node.range.len.min(FRAME_HEADER_MAX),
+            },
+        )?;
+        match zstd::zstd_safe::get_frame_content_size(&header) {
+            Ok(Some(size)) => Ok(size),
+            _ => Ok(self.read_node(node)?.len() as u64),
+        }
+    }
+
+    fn read_node_uncached(&self, node: layout::Node) -> io::Result<Vec<u8>> {
+        self.check_range(node.range)?;
         let data = Self::read_range(&self.file, node.range)?;
         if node.flags & layout::FLAG_COMPRESSED != 0 {
-            let Some(dict) = &self.dict else {
+            self.count(|c| &c.decompressions);
+        }
+        let data = if node.flags & layout::FLAG_COMPRESSED != 0 {
+            let mut res = Vec::new();
+            match &self.dict {
+                Some(dict) if node.flags & layout::FLAG_NO_DICT == 0 => {
+                    Decoder::with_prepared_dictionary(&data[..], dict)?.read_to_end(&mut res)?
+                }
+                // The writer falls back to an empty dictionary if training fails, which isn't stored.
+                _ => Decoder::new(&data[..])?.read_to_end(&mut res)?,
+            };
+            res
+        } else {
+            data
+        };
+
+        if self.verify && self.checksums.is_some() {
+            let Some(expected) = self.checksum(node)? else {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
-                    "node is compressed, but zup has no dictionary",
+                    "node has no checksum",
                 ));
             };
-            let mut res = Vec::new();
-            let mut dec = Decoder::with_prepared_dictionary(&data[..], dict)?;
-            dec.read_to_end(&mut res)?;
-            Ok(res)
-        } else {
-            Ok(data)
+            if *blake3::hash(&data).as_bytes() != expected {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "checksum mismatch",
+                ));
+            }
         }
+
+        Ok(data)
     }
 
     pub fn root_node(&self) -> Node<'_> {
@@ -105,16 +404,12 @@ impl Reader {
                     ));
                 }
                 Node::Directory(dir) => {
-                    let (_, child) = dir
-                        .children()?
-                        .into_iter()
-                        .find(|(name, _)| name == segment)
-                        .ok_or_else(|| {
-                            io::Error::new(
-                                io::ErrorKind::NotFound,
-                                format!("not found: {}", path[..i + 1].join("/")),
-                            )
-                        })?;
+                    let child = dir.child(segment)?.ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!("not found: {}", path[..i + 1].join("/")),
+                        )
+                    })?;
                     node = child
                 }
             }
@@ -124,12 +419,10 @@ impl Reader {
 
     pub fn read(&self, path: &[&str]) -> io::Result<Vec<u8>> {
         match self.open(path)? {
-            Node::Directory(_) => {
-                return Err(io::Error::new(
-                    io::ErrorKind::IsADirectory,
-                    format!("is a directory, not a file: {}", path.join("/")),
-                ));
-            }
+            Node::Directory(_) => Err(io::Error::new(
+                io::ErrorKind::IsADirectory,
+                format!("is a directory, not a file: {}", path.join("/")),
+            )),
             Node::File(f) => f.read(),
         }
     }
@@ -159,7 +452,98 @@ impl<'a> File<'a> {
         self.node
     }
     pub fn read(&self) -> io::Result<Vec<u8>> {
-        self.reader.read_node(self.node)
+        Ok(self.reader.read_node(self.node)?.into())
+    }
+
+    /// Uncompressed size of the file.
+    pub fn size(&self) -> io::Result<u64> {
+        self.reader.node_size(self.node)
+    }
+
+    /// Open the stored zstd frame for reading as-is, if it can be decompressed on its own.
+    ///
+    /// Returns `None` unless the file was compressed without the archive's dictionary. Like
+    /// `reader`, this streams the frame and isn't subject to the maximum range size.
+    pub fn zstd_frame(&self) -> io::Result<Option<RangeReader<'a>>> {
+        if self.node.flags & layout::FLAG_NO_DICT == 0 {
+            return Ok(None);
+        }
+        let reader = self.reader;
+        reader.check_range(self.node.range)?;
+        Ok(Some(RangeReader {
+            file: &reader.file,
+            offset: self.node.range.offset,
+            remaining: self.node.range.len,
+        }))
+    }
+
+    /// Open the file for incremental reading.
+    ///
+    /// Unlike `read`, this never holds the whole file in memory and isn't
+    /// subject to the maximum range size, so use it for large files. Content
+    /// read this way is not checked against stored checksums.
+    pub fn reader(&self) -> io::Result<FileReader<'a>> {
+        let reader = self.reader;
+        reader.check_range(self.node.range)?;
+        let raw = RangeReader {
+            file: &reader.file,
+            offset: self.node.range.offset,
+            remaining: self.node.range.len,
+        };
+        if self.node.flags & layout::FLAG_COMPRESSED == 0 {
+            return Ok(FileReader::Raw(raw));
+        }
+        reader.count(|c| &c.decompressions);
+        let dec = match &reader.dict {
+            Some(dict) if self.node.flags & layout::FLAG_NO_DICT == 0 => {
+                Decoder::with_prepared_dictionary(BufReader::new(raw), dict)?
+            }
+            _ => Decoder::new(raw)?,
+        };
+        Ok(FileReader::Compressed(Box::new(dec.single_frame())))
+    }
+}
+
+/// Incremental reader for the contents of a `File`, see `File::reader`.
+pub enum FileReader<'a> {
+    Raw(RangeReader<'a>),
+    Compressed(Box<Decoder<'a, BufReader<RangeReader<'a>>>>),
+}
+
+impl Read for FileReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Raw(r) => r.read(buf),
+            Self::Compressed(r) => r.read(buf),
+        }
+    }
+}
+
+/// Reads one `layout::Range` of the archive file with positional reads.
+pub struct RangeReader<'a> {
+    file: &'a fs::File,
+    offset: u64,
+    remaining: u64,
+}
+
+impl Read for RangeReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf
+            .len()
+            .min(self.remaining.try_into().unwrap_or(usize::MAX));
+        if len == 0 {
+            return Ok(0);
+        }
+        let n = read_at(self.file, &mut buf[..len], self.offset)?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "unexpected end of zup",
+            ));
+        }
+        self.offset += n as u64;
+        self.remaining -= n as u64;
+        Ok(n)
     }
 }
 
@@ -173,33 +557,116 @@ impl<'a> Directory<'a> {
         self.node
     }
 
+    fn make_node(&self, node: layout::Node) -> Node<'a> {
+        if node.flags & layout::FLAG_DIR != 0 {
+            Node::Directory(Directory {
+                reader: self.reader,
+                node,
+            })
+        } else {
+            Node::File(File {
+                reader: self.reader,
+                node,
+            })
+        }
+    }
+
+    fn indexed(&self) -> bool {
+        self.reader.superblock.version >= layout::VERSION_INDEXED_DIRS
+    }
+
     pub fn children(&self) -> io::Result<Vec<(String, Node<'a>)>> {
-        let data = self.reader.read_node(self.node).unwrap();
-        let data = ByteReader::new(&data);
+        let data = self.reader.read_node(self.node)?;
 
         let mut res = Vec::new();
 
+        if self.indexed() {
+            let index = DirIndex::new(&data)?;
+            for i in 0..index.len() {
+                let (name, node) = index.get(i)?;
+                res.push((utf8_name(name)?.to_string(), self.make_node(node)));
+            }
+            return Ok(res);
+        }
+
+        let data = ByteReader::new(&data);
         while !data.eof() {
-            let name = str::from_utf8(data.read_slice_len8()?)
-                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid utf8 filename"))?
-                .to_string();
+            let name = utf8_name(data.read_slice_len8()?)?.to_string();
             let node = layout::Node::from_bytes(data.read()?);
-            let node = if node.flags & layout::FLAG_DIR != 0 {
-                Node::Directory(Directory {
-                    reader: self.reader,
-                    node,
-                })
-            } else {
-                Node::File(File {
-                    reader: self.reader,
-                    node,
-                })
-            };
-            res.push((name, node));
+            res.push((name, self.make_node(node)));
         }
 
         Ok(res)
     }
+
+    /// Look up a single child by name.
+    ///
+    /// On indexed directories this is a binary search that doesn't decode the other entries.
+    pub fn child(&self, name: &str) -> io::Result<Option<Node<'a>>> {
+        if !self.indexed() {
+            return Ok(self
+                .children()?
+                .into_iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, node)| node));
+        }
+
+        let data = self.reader.read_node(self.node)?;
+        let index = DirIndex::new(&data)?;
+        Ok(index
+            .find(name.as_bytes())?
+            .map(|node| self.make_node(node)))
+    }
+}
+
+fn utf8_name(name: &[u8]) -> io::Result<&str> {
+    str::from_utf8(name)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid utf8 filename"))
+}
+
+/// View into the sorted entry index of a version 2+ directory node.
+struct DirIndex<'d> {
+    entries: &'d [u8],
+    names: &'d [u8],
+}
+
+impl<'d> DirIndex<'d> {
+    fn new(data: &'d [u8]) -> io::Result<Self> {
+        let data = ByteReader::new(data);
+        let count = data.read_u32()? as usize;
+        let entries = data.read_slice(count * layout::DirEntry::LEN)?;
+        let names = data.read_rest();
+        Ok(Self { entries, names })
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len() / layout::DirEntry::LEN
+    }
+
+    fn get(&self, i: usize) -> io::Result<(&'d [u8], layout::Node)> {
+        let entry = &self.entries[i * layout::DirEntry::LEN..][..layout::DirEntry::LEN];
+        let entry = layout::DirEntry::from_bytes(entry.try_into().unwrap());
+        let name = self
+            .names
+            .get(entry.name_offset as usize..)
+            .and_then(|n| n.get(..entry.name_len as usize))
+            .ok_or(ReadError)?;
+        Ok((name, entry.node))
+    }
+
+    fn find(&self, name: &[u8]) -> io::Result<Option<layout::Node>> {
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let (n, node) = self.get(mid)?;
+            match n.cmp(name) {
+                Ordering::Equal => return Ok(Some(node)),
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+            }
+        }
+        Ok(None)
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -241,7 +708,6 @@ impl<'a> ByteReader<'a> {
         Ok(u16::from_le_bytes(self.read()?))
     }
 
-    #[allow(dead_code)]
     fn read_u32(&self) -> Result<u32, ReadError> {
         Ok(u32::from_le_bytes(self.read()?))
     }
@@ -251,20 +717,208 @@ impl<'a> ByteReader<'a> {
         Ok(u64::from_le_bytes(self.read()?))
     }
 
-    fn read_slice(&self, len: usize) -> Result<&[u8], ReadError> {
+    fn read_slice(&self, len: usize) -> Result<&'a [u8], ReadError> {
         let res = self.data.get().get(0..len).ok_or(ReadError)?;
         self.data.set(&self.data.get()[len..]);
         Ok(res)
     }
 
-    fn read_slice_len8(&self) -> Result<&[u8], ReadError> {
+    fn read_rest(&self) -> &'a [u8] {
+        self.data.replace(&[])
+    }
+
+    fn read_slice_len8(&self) -> Result<&'a [u8], ReadError> {
         let len = self.read_u8()? as usize;
         self.read_slice(len)
     }
 
     #[allow(dead_code)]
-    fn read_slice_len16(&self) -> Result<&[u8], ReadError> {
+    fn read_slice_len16(&self) -> Result<&'a [u8], ReadError> {
         let len = self.read_u16()? as usize;
         self.read_slice(len)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::zup::write::{self, CompressConfig};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("zup-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A small docs-like tree: (path, contents).
+    fn sample_files() -> Vec<(String, Vec<u8>)> {
+        let mut res = vec![
+            ("index.html".to_string(), b"<html>root</html>".to_vec()),
+            ("gpio/index.html".to_string(), b"<html>gpio</html>".to_vec()),
+            (
+                "gpio/struct.Output.html".to_string(),
+                b"<html>Output</html>".repeat(100),
+            ),
+            // Same content as above, deduplicated by the writer.
+            (
+                "gpio/struct.Input.html".to_string(),
+                b"<html>Output</html>".repeat(100),
+            ),
+            (
+                "static.files/main.js".to_string(),
+                b"let x = 1;".repeat(500),
+            ),
+        ];
+        // Enough entries for the binary search to take a few steps.
+        for i in 0..50 {
+            res.push((
+                format!("pac/fn.reg{:02}.html", i),
+                format!("<html>reg {}</html>", i).into_bytes(),
+            ));
+        }
+        res
+    }
+
+    fn check_round_trip(name: &str, compress: Option<CompressConfig>) {
+        let dir = temp_dir(name);
+        let input = dir.join("in");
+        let files = sample_files();
+        for (path, data) in &files {
+            let path = input.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        }
+        let output = dir.join("out.zup");
+        write::pack(&input, &output, compress).unwrap();
+
+        let zup = Reader::new(&output).unwrap();
+        assert_eq!(zup.superblock.version, layout::VERSION);
+
+        let Node::Directory(root) = zup.root_node() else {
+            panic!("root is not a directory");
+        };
+        let names: Vec<_> = root
+            .children()
+            .unwrap()
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect();
+        assert_eq!(names, ["gpio", "index.html", "pac", "static.files"]);
+
+        let Some(Node::Directory(pac)) = root.child("pac").unwrap() else {
+            panic!("pac is not a directory");
+        };
+        let children = pac.children().unwrap();
+        assert_eq!(children.len(), 50);
+        assert!(children.windows(2).all(|w| w[0].0 < w[1].0));
+        for (name, _) in &children {
+            assert!(matches!(pac.child(name).unwrap(), Some(Node::File(_))));
+        }
+        for missing in ["", "a", "fn.reg", "fn.reg50.html", "zzz"] {
+            assert!(pac.child(missing).unwrap().is_none(), "{:?}", missing);
+        }
+
+        for (path, data) in &files {
+            let path: Vec<&str> = path.split('/').collect();
+            let Node::File(f) = zup.open(&path).unwrap() else {
+                panic!("{:?} is not a file", path);
+            };
+            assert_eq!(&f.read().unwrap(), data);
+            assert_eq!(f.size().unwrap(), data.len() as u64);
+            let mut streamed = Vec::new();
+            f.reader().unwrap().read_to_end(&mut streamed).unwrap();
+            assert_eq!(&streamed, data);
+        }
+        assert_eq!(
+            zup.open(&["gpio", "nope.html"]).err().map(|e| e.kind()),
+            Some(io::ErrorKind::NotFound)
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn round_trip_uncompressed() {
+        check_round_trip("uncompressed", None);
+    }
+
+    #[test]
+    fn round_trip_compressed() {
+        check_round_trip(
+            "compressed",
+            Some(CompressConfig {
+                level: 3,
+                dict_size: 4096,
+                dict_train_size: 1_000_000,
+                dictless_assets: true,
+            }),
+        );
+    }
+
+    #[test]
+    fn reads_version_1() {
+        let dir = temp_dir("v1");
+        let path = dir.join("v1.zup");
+        let mut f = fs::File::create(&path).unwrap();
+        let mut offset = 0;
+        let mut write_node = |f: &mut fs::File, data: &[u8], flags: u32| {
+            f.write_all(data).unwrap();
+            let range = layout::Range {
+                offset,
+                len: data.len() as u64,
+            };
+            offset += range.len;
+            layout::Node { flags, range }
+        };
+        // Version 1 directories are a plain list of (len8 name, node), in no particular order.
+        let v1_dir = |entries: &[(&str, layout::Node)]| {
+            let mut res = Vec::new();
+            for (name, node) in entries {
+                res.push(name.len() as u8);
+                res.extend_from_slice(name.as_bytes());
+                res.extend_from_slice(&node.to_bytes());
+            }
+            res
+        };
+
+        let hello = write_node(&mut f, b"hello", 0);
+        let world = write_node(&mut f, b"world", 0);
+        let sub = v1_dir(&[("x.txt", world)]);
+        let sub = write_node(&mut f, &sub, layout::FLAG_DIR);
+        let root = v1_dir(&[("sub", sub), ("b.txt", hello)]);
+        let root = write_node(&mut f, &root, layout::FLAG_DIR);
+        let superblock = layout::Superblock {
+            dict: None,
+            root,
+            version: 1,
+            magic: layout::MAGIC,
+        };
+        f.write_all(&superblock.to_bytes()).unwrap();
+        drop(f);
+
+        let zup = Reader::new(&path).unwrap();
+        let Node::Directory(root) = zup.root_node() else {
+            panic!("root is not a directory");
+        };
+        let names: Vec<_> = root
+            .children()
+            .unwrap()
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect();
+        assert_eq!(names, ["sub", "b.txt"]);
+        assert!(matches!(root.child("b.txt").unwrap(), Some(Node::File(_))));
+        assert!(matches!(
+            root.child("sub").unwrap(),
+            Some(Node::Directory(_))
+        ));
+        assert!(root.child("c.txt").unwrap().is_none());
+        assert_eq!(zup.read(&["b.txt"]).unwrap(), b"hello");
+        assert_eq!(zup.read(&["sub", "x.txt"]).unwrap(), b"world");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
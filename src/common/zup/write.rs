@@ -19,6 +19,7 @@ pub struct CompressConfig {
     pub level: i32,
     pub dict_size: usize,
     pub dict_train_size: usize,
+    pub dictless_assets: bool,
 }
 
 #[derive(Default)]
@@ -144,7 +145,11 @@ pub fn pack(
                     })
             };
 
-            Some(WriterCompress::from_dict(compress.level, dict)?)
+            Some(WriterCompress::from_dict(
+                compress.level,
+                dict,
+                compress.dictless_assets,
+            )?)
         }
         None => None,
     };
@@ -181,52 +186,85 @@ struct Writer {
 struct WriterCompress {
     dict: Vec<u8>,
     comp: Compressor<'static>,
+    /// Compressor without the dictionary, for non-HTML files if enabled.
+    dictless: Option<Compressor<'static>>,
 }
 
 impl WriterCompress {
-    pub fn from_dict(level: i32, dict: Vec<u8>) -> io::Result<Self> {
+    pub fn from_dict(level: i32, dict: Vec<u8>, dictless_assets: bool) -> io::Result<Self> {
         let comp = Compressor::with_dictionary(level, &dict)?;
+        let dictless = match dictless_assets {
+            true => Some(Compressor::new(level)?),
+            false => None,
+        };
 
-        Ok(Self { dict, comp })
+        Ok(Self {
+            dict,
+            comp,
+            dictless,
+        })
     }
 
-    pub fn compress(&mut self, data: &[u8]) -> Result<Vec<u8>, io::Error> {
-        self.comp.compress(data)
+    /// Compress `data`, returning the compressed data and the node flags to use.
+    pub fn compress(&mut self, data: &[u8], asset: bool) -> Result<(Vec<u8>, u32), io::Error> {
+        match &mut self.dictless {
+            Some(comp) if asset => Ok((
+                comp.compress(data)?,
+                layout::FLAG_COMPRESSED | layout::FLAG_NO_DICT,
+            )),
+            _ => Ok((self.comp.compress(data)?, layout::FLAG_COMPRESSED)),
+        }
     }
 }
 
 impl Writer {
     fn write(&mut self, path: &Path, cache: &FileCache) -> io::Result<layout::Node> {
-        let m = fs::metadata(&path)?;
+        let m = fs::metadata(path)?;
         if m.is_dir() {
             self.stats.total_dirs += 1;
 
-            let mut readdir: Vec<_> = fs::read_dir(&path)?.collect::<Result<Vec<_>, _>>()?;
-            readdir.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+            let mut readdir: Vec<_> = fs::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
+            readdir.sort_by_key(|a| a.file_name());
 
-            let mut buf = Vec::new();
+            let mut entries = Vec::new();
             for entry in readdir {
                 let node = self.write(&entry.path(), cache)?;
                 let name = entry.file_name().to_string_lossy().to_string();
+                entries.push((name, node));
+            }
+            // Lossy conversion can change the order, the index must be sorted by the final name.
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
 
-                buf.push(name.len().try_into().unwrap());
+            let mut buf = Vec::new();
+            buf.extend_from_slice(&u32::try_from(entries.len()).unwrap().to_le_bytes());
+            let mut name_offset = 0;
+            for (name, node) in &entries {
+                let entry = layout::DirEntry {
+                    name_offset,
+                    name_len: name.len().try_into().unwrap(),
+                    node: *node,
+                };
+                buf.extend_from_slice(&entry.to_bytes());
+                name_offset += u32::from(entry.name_len);
+            }
+            for (name, _) in &entries {
                 buf.extend_from_slice(name.as_bytes());
-                buf.extend_from_slice(&node.to_bytes());
             }
 
-            let mut res = self.write_node(&buf, None)?;
+            let mut res = self.write_node(&buf, None, false)?;
             res.flags |= layout::FLAG_DIR;
             Ok(res)
         } else {
             self.stats.total_files += 1;
 
-            let (buf, cached_hash) = if let Some((buf, cached_hash)) = cache.get(&path) {
+            let (buf, cached_hash) = if let Some((buf, cached_hash)) = cache.get(path) {
                 (Cow::from(buf), Some(cached_hash))
             } else {
                 (Cow::from(fs::read(path)?), None)
             };
 
-            let res = self.write_node(&buf, cached_hash)?;
+            let asset = path.extension().is_none_or(|e| e != "html");
+            let res = self.write_node(&buf, cached_hash, asset)?;
             Ok(res)
         }
     }
@@ -235,6 +273,7 @@ impl Writer {
         &mut self,
         buf: impl AsRef<[u8]>,
         cached_hash: Option<[u8; 32]>,
+        asset: bool,
     ) -> io::Result<layout::Node> {
         let mut buf: Cow<[u8]> = Cow::Borrowed(buf.as_ref());
         // Track stats before dedup
@@ -253,11 +292,11 @@ impl Writer {
 
         let mut flags = 0;
         if let Some(comp) = &mut self.comp
-            && let Ok(cdata) = comp.compress(&buf)
+            && let Ok((cdata, cflags)) = comp.compress(&buf, asset)
             && cdata.len() < buf.len()
         {
             buf = cdata.into();
-            flags = layout::FLAG_COMPRESSED;
+            flags = cflags;
         }
 
         self.stats.compressed_bytes_before_dedup += buf.len() as u64;
@@ -342,6 +381,24 @@ impl Writer {
             None => None,
         };
 
+        // One checksum per unique node, sorted so readers can binary search it.
+        let mut checksums: Vec<_> = self
+            .hash_dedup
+            .iter()
+            .map(|(hash, node)| layout::Checksum {
+                range: node.range,
+                hash: *hash,
+            })
+            .collect();
+        checksums.sort_by_key(|c| (c.range.offset, c.range.len));
+        let mut buf = Vec::with_capacity(checksums.len() * layout::Checksum::LEN);
+        for c in checksums {
+            buf.extend_from_slice(&c.to_bytes());
+        }
+        let checksums = self.write_data(&buf)?;
+        self.f
+            .write_all(&layout::SuperblockExt { checksums }.to_bytes())?;
+
         let superblock = layout::Superblock {
             version: layout::VERSION,
             magic: layout::MAGIC,
@@ -18,6 +18,10 @@ enum Commands {
     Build(commands::build::BuildArgs),
     /// Build documentation archives from crates.io release
     BuildRelease(commands::build_release::BuildReleaseArgs),
+    /// Print a file from a zup archive
+    Cat(commands::cat::CatArgs),
+    /// List a directory inside a zup archive
+    Ls(commands::ls::LsArgs),
     /// Serve documentation from archives
     Serve(commands::serve::ServeArgs),
     /// Extract zup archives
@@ -35,6 +39,8 @@ async fn main() -> anyhow::Result<()> {
     match cli.command {
         Commands::Build(args) => commands::build::run(args).await,
         Commands::BuildRelease(args) => commands::build_release::run(args).await,
+        Commands::Cat(args) => commands::cat::run(args).await,
+        Commands::Ls(args) => commands::ls::run(args).await,
         Commands::Serve(args) => commands::serve::run(args).await,
         Commands::Unzup(args) => commands::unzup::run(args).await,
         Commands::Zup(args) => commands::zup::run(args).await,